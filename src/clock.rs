@@ -0,0 +1,224 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::http::HttpResponse;
+use crate::service_error::ServiceError;
+
+/// Smaller skews are not corrected by `SkewCorrectedClock::update_from_response`: they are within
+/// what AWS tolerates, and may just be network latency.
+pub const MIN_CLOCK_SKEW: Duration = Duration::from_secs(4 * 60);
+
+/// A source of the current time, used to timestamp signatures and to check expirations.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+impl<T: Clock + ?Sized> Clock for Arc<T> {
+    fn now(&self) -> DateTime<Utc> {
+        (**self).now()
+    }
+}
+
+/// The host's clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that always returns the same time, for tests and for signing with a chosen timestamp.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+/// A clock that adds an offset to another clock, to correct for a host clock that disagrees with
+/// AWS. The offset can be updated while the clock is shared (e.g. in an `Arc`).
+pub struct SkewCorrectedClock {
+    base: Box<dyn Clock>,
+    offset_millis: AtomicI64,
+}
+
+impl SkewCorrectedClock {
+    /// A skew-corrected system clock, with no offset.
+    pub fn new() -> SkewCorrectedClock {
+        SkewCorrectedClock::with_clock(Box::new(SystemClock))
+    }
+
+    pub fn with_clock(base: Box<dyn Clock>) -> SkewCorrectedClock {
+        SkewCorrectedClock {
+            base,
+            offset_millis: AtomicI64::new(0),
+        }
+    }
+
+    /// The offset added to the base clock (the server's time minus the local time).
+    pub fn offset(&self) -> chrono::Duration {
+        chrono::Duration::milliseconds(self.offset_millis.load(Ordering::Relaxed))
+    }
+
+    pub fn set_offset(&self, offset: chrono::Duration) {
+        self.offset_millis
+            .store(offset.num_milliseconds(), Ordering::Relaxed);
+    }
+
+    /// Set the offset so that this clock agrees with `server_time` (the server's current time).
+    /// Returns the new offset.
+    pub fn update_from_server_time(&self, server_time: DateTime<Utc>) -> chrono::Duration {
+        let offset = server_time - self.base.now();
+        self.set_offset(offset);
+        offset
+    }
+
+    /// Learn the offset from the value of a server's `Date` response header (an RFC 1123 date,
+    /// e.g. `Sun, 30 Aug 2015 12:36:00 GMT`). Returns the new offset, or `None` (leaving the
+    /// offset unchanged) if the header can't be parsed.
+    pub fn update_from_date_header(&self, date: &str) -> Option<chrono::Duration> {
+        let server_time = parse_http_date(date)?;
+        Some(self.update_from_server_time(server_time))
+    }
+
+    /// Correct the offset if `response` is an AWS error caused by clock skew (see
+    /// `ServiceError::is_clock_skew`), so that retrying the request with a new signature succeeds.
+    /// The server's time is taken from the `Date` header, or else from the error's `ServerTime`.
+    ///
+    /// Returns the new offset, or `None` if the response isn't a clock skew error or if the server
+    /// is within `MIN_CLOCK_SKEW` of this clock (in which case the error has some other cause).
+    pub fn update_from_response(&self, response: &HttpResponse) -> Option<chrono::Duration> {
+        if response.is_success() {
+            return None;
+        }
+        let error = ServiceError::parse(&response.body_string())?;
+        if !error.is_clock_skew() {
+            return None;
+        }
+
+        let server_time = response
+            .header("Date")
+            .and_then(parse_http_date)
+            .or(error.server_time)?;
+        let skew = (server_time - self.now()).num_milliseconds().unsigned_abs();
+        if u128::from(skew) < MIN_CLOCK_SKEW.as_millis() {
+            return None;
+        }
+        Some(self.update_from_server_time(server_time))
+    }
+}
+
+impl Default for SkewCorrectedClock {
+    fn default() -> SkewCorrectedClock {
+        SkewCorrectedClock::new()
+    }
+}
+
+impl Clock for SkewCorrectedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.base.now() + self.offset()
+    }
+}
+
+fn parse_http_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(date.trim())
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::clock::*;
+
+    #[test]
+    fn test_fixed_clock() {
+        let time = Utc.ymd_opt(2015, 8, 30).and_hms_opt(12, 36, 0).unwrap();
+        let clock: Arc<dyn Clock> = Arc::new(FixedClock(time));
+        assert_eq!(time, clock.now());
+        assert_eq!(time, clock.clone().now());
+    }
+
+    #[test]
+    fn test_skew_corrected_clock() {
+        let local = Utc.ymd_opt(2015, 8, 30).and_hms_opt(12, 36, 0).unwrap();
+        let clock = SkewCorrectedClock::with_clock(Box::new(FixedClock(local)));
+        assert_eq!(local, clock.now());
+
+        assert_eq!(
+            Some(chrono::Duration::seconds(-330)),
+            clock.update_from_date_header("Sun, 30 Aug 2015 12:30:30 GMT")
+        );
+        assert_eq!(
+            Utc.ymd_opt(2015, 8, 30).and_hms_opt(12, 30, 30).unwrap(),
+            clock.now()
+        );
+
+        assert_eq!(None, clock.update_from_date_header("yesterday"));
+        assert_eq!(chrono::Duration::seconds(-330), clock.offset());
+
+        clock.set_offset(chrono::Duration::hours(1));
+        assert_eq!(local + chrono::Duration::hours(1), clock.now());
+    }
+
+    fn skew_error(code: &str, date: Option<&str>) -> HttpResponse {
+        HttpResponse {
+            status: 403,
+            headers: date
+                .map(|date| vec![("Date".to_string(), date.to_string())])
+                .unwrap_or_default(),
+            body: format!(
+                "<Error><Code>{}</Code><Message>message</Message>\
+                 <ServerTime>2015-08-30T13:06:00Z</ServerTime></Error>",
+                code
+            )
+            .into_bytes(),
+        }
+    }
+
+    #[test]
+    fn test_update_from_response() {
+        let local = Utc.ymd_opt(2015, 8, 30).and_hms_opt(12, 36, 0).unwrap();
+        let clock = SkewCorrectedClock::with_clock(Box::new(FixedClock(local)));
+
+        let date = Some("Sun, 30 Aug 2015 12:56:00 GMT");
+        assert_eq!(
+            None,
+            clock.update_from_response(&skew_error("AccessDenied", date))
+        );
+        assert_eq!(
+            Some(chrono::Duration::minutes(20)),
+            clock.update_from_response(&skew_error("RequestTimeTooSkewed", date))
+        );
+        // Without a Date header, S3's ServerTime is used
+        assert_eq!(
+            Some(chrono::Duration::minutes(30)),
+            clock.update_from_response(&skew_error("RequestTimeTooSkewed", None))
+        );
+        // The clock now agrees with the server, so the signature is wrong for some other reason
+        assert_eq!(
+            None,
+            clock.update_from_response(&skew_error(
+                "SignatureDoesNotMatch",
+                Some("Sun, 30 Aug 2015 13:07:00 GMT")
+            ))
+        );
+        assert_eq!(chrono::Duration::minutes(30), clock.offset());
+
+        let success = HttpResponse {
+            status: 200,
+            ..skew_error("RequestTimeTooSkewed", date)
+        };
+        assert_eq!(None, clock.update_from_response(&success));
+    }
+}
@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+
+use crate::error;
+use crate::json;
+use crate::presigner::SigningCredentials;
+
+pub mod chain;
+pub mod container;
+pub mod environment;
+pub mod imds;
+pub mod process;
+pub mod profile;
+pub mod sts;
+pub mod web_identity;
+
+/// A source of credentials that can be used to sign requests.
+pub trait CredentialsProvider: Send + Sync {
+    fn credentials(&self) -> Result<SigningCredentials, error::Error>;
+}
+
+impl CredentialsProvider for SigningCredentials {
+    fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+        Ok(self.clone())
+    }
+}
+
+/// Parse the JSON credentials document used by the instance metadata service, the container
+/// credentials endpoint and `credential_process`. They differ only in the name of the session
+/// token property.
+pub(crate) fn credentials_from_json(
+    document: &str,
+    session_token_key: &str,
+) -> Result<SigningCredentials, error::Error> {
+    credentials_from_value(&json::parse(document)?, session_token_key)
+}
+
+pub(crate) fn credentials_from_value(
+    value: &json::Value,
+    session_token_key: &str,
+) -> Result<SigningCredentials, error::Error> {
+    let required = |key: &str| {
+        value
+            .get_str(key)
+            .map(|v| v.to_string())
+            .ok_or_else(|| error::Error::parse(&format!("credentials document is missing {}", key)))
+    };
+
+    Ok(SigningCredentials {
+        access_key_id: required("AccessKeyId")?,
+        secret_access_key: required("SecretAccessKey")?,
+        session_token: value.get_str(session_token_key).map(|v| v.to_string()),
+        expiration: value
+            .get_str("Expiration")
+            .map(parse_expiration)
+            .transpose()?,
+    })
+}
+
+pub(crate) fn parse_expiration(value: &str) -> Result<DateTime<Utc>, error::Error> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| error::Error::Parse {
+            message: format!("invalid expiration {}", value),
+            source: Some(Box::new(e)),
+        })
+}
@@ -0,0 +1,390 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::clock::{Clock, SystemClock};
+use crate::credentials::container::ContainerProvider;
+use crate::credentials::environment::EnvironmentProvider;
+use crate::credentials::imds::ImdsProvider;
+use crate::credentials::profile::ProfileProvider;
+use crate::credentials::web_identity::WebIdentityProvider;
+use crate::credentials::CredentialsProvider;
+use crate::error;
+use crate::http::HttpClient;
+use crate::presigner::SigningCredentials;
+
+/// How long before expiry cached credentials are refreshed by default.
+pub const DEFAULT_REFRESH_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Tries each provider in turn, returning the first credentials found.
+///
+/// Providers that report `Error::CredentialsMissing` (i.e. they are not configured) are
+/// skipped; any other error stops the chain, so that a misconfigured source is not silently
+/// bypassed.
+pub struct ChainProvider {
+    providers: Vec<Box<dyn CredentialsProvider>>,
+}
+
+impl ChainProvider {
+    pub fn new(providers: Vec<Box<dyn CredentialsProvider>>) -> ChainProvider {
+        ChainProvider { providers }
+    }
+
+    /// The standard chain: environment, profile, web identity, container, instance metadata.
+    ///
+    /// There is no client that can reach STS, so role profiles and web identity tokens are an
+    /// error (rather than being skipped in favour of, say, the instance's role); use
+    /// `default_chain_with_http_client` to support them.
+    pub fn default_chain() -> ChainProvider {
+        ChainProvider::new(vec![
+            Box::new(EnvironmentProvider::new()),
+            Box::new(ProfileProvider::new()),
+            Box::new(WebIdentityProvider::new()),
+            Box::new(ContainerProvider::new()),
+            Box::new(ImdsProvider::new()),
+        ])
+    }
+
+    /// The standard chain: environment, profile, web identity, container, instance metadata.
+    /// `client` is used to call STS, so it must support HTTPS. STS requests are signed with the
+    /// time from `clock`, which may be a shared `SkewCorrectedClock`.
+    pub fn default_chain_with_http_client(
+        client: Arc<dyn HttpClient>,
+        clock: Arc<dyn Clock>,
+    ) -> ChainProvider {
+        ChainProvider::new(vec![
+            Box::new(EnvironmentProvider::new()),
+            Box::new(
+                ProfileProvider::new()
+                    .with_http_client(client.clone())
+                    .with_clock(clock.clone()),
+            ),
+            Box::new(
+                WebIdentityProvider::new()
+                    .with_http_client(Box::new(client))
+                    .with_clock(Box::new(clock)),
+            ),
+            Box::new(ContainerProvider::new()),
+            Box::new(ImdsProvider::new()),
+        ])
+    }
+}
+
+impl CredentialsProvider for ChainProvider {
+    fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+        let mut missing = vec![];
+        for provider in &self.providers {
+            match provider.credentials() {
+                Ok(credentials) => return Ok(credentials),
+                Err(error::Error::CredentialsMissing(message)) => missing.push(message),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(error::Error::CredentialsMissing(format!(
+            "no credentials found: {}",
+            missing.join("; ")
+        )))
+    }
+}
+
+/// Caches the credentials returned by another provider, fetching new ones once they are within
+/// `refresh_window` of their expiration. Credentials without an expiration are cached forever. If
+/// a refresh fails, the cached credentials are used for as long as they haven't expired.
+pub struct CachingProvider {
+    inner: Box<dyn CredentialsProvider>,
+    refresh_window: Duration,
+    cached: Mutex<Option<SigningCredentials>>,
+    clock: Box<dyn Clock>,
+}
+
+impl CachingProvider {
+    pub fn new(inner: Box<dyn CredentialsProvider>, refresh_window: Duration) -> CachingProvider {
+        CachingProvider {
+            inner,
+            refresh_window,
+            cached: Mutex::new(None),
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Check expirations against the time from `clock`.
+    pub fn with_clock(mut self, clock: Box<dyn Clock>) -> CachingProvider {
+        self.clock = clock;
+        self
+    }
+
+    /// Discard the cached credentials, so that the next call fetches new ones.
+    pub fn invalidate(&self) {
+        *self.cached.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    fn is_expired(&self, credentials: &SigningCredentials) -> bool {
+        credentials
+            .expiration
+            .is_some_and(|expiration| expiration <= self.clock.now())
+    }
+
+    fn is_fresh(&self, credentials: &SigningCredentials) -> bool {
+        match credentials.expiration {
+            None => true,
+            Some(expiration) => match chrono::Duration::from_std(self.refresh_window) {
+                Ok(window) => expiration.signed_duration_since(self.clock.now()) > window,
+                // A window too large to represent includes every expiration
+                Err(_) => false,
+            },
+        }
+    }
+}
+
+impl CredentialsProvider for CachingProvider {
+    fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+        // Holding the lock while fetching means concurrent callers wait for a single refresh
+        // rather than all hitting the underlying provider at once
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(credentials) = cached.as_ref() {
+            if self.is_fresh(credentials) {
+                return Ok(credentials.clone());
+            }
+        }
+
+        match self.inner.credentials() {
+            Ok(credentials) => {
+                *cached = Some(credentials.clone());
+                Ok(credentials)
+            }
+            Err(e) => match cached.as_ref() {
+                Some(credentials) if !self.is_expired(credentials) => Ok(credentials.clone()),
+                _ => Err(e),
+            },
+        }
+    }
+}
+
+/// The default credentials provider: `ChainProvider::default_chain`, cached and refreshed five
+/// minutes before expiry.
+pub fn default_provider() -> CachingProvider {
+    CachingProvider::new(
+        Box::new(ChainProvider::default_chain()),
+        DEFAULT_REFRESH_WINDOW,
+    )
+}
+
+/// `default_provider`, using `ChainProvider::default_chain_with_http_client`. `clock` is also used
+/// to check the cached credentials' expiration.
+pub fn default_provider_with_http_client(
+    client: Arc<dyn HttpClient>,
+    clock: Arc<dyn Clock>,
+) -> CachingProvider {
+    CachingProvider::new(
+        Box::new(ChainProvider::default_chain_with_http_client(
+            client,
+            clock.clone(),
+        )),
+        DEFAULT_REFRESH_WINDOW,
+    )
+    .with_clock(Box::new(clock))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use chrono::{DateTime, TimeZone, Utc};
+
+    use crate::clock::{FixedClock, SkewCorrectedClock};
+    use crate::credentials::chain::*;
+    use crate::credentials::web_identity::WebIdentitySettings;
+    use crate::http::{test_server, TcpHttpClient};
+
+    struct CountingProvider {
+        calls: Arc<AtomicUsize>,
+        expiration: Option<DateTime<Utc>>,
+    }
+
+    impl CredentialsProvider for CountingProvider {
+        fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(SigningCredentials {
+                access_key_id: format!("AKID{}", call),
+                secret_access_key: "secret".to_string(),
+                session_token: None,
+                expiration: self.expiration,
+            })
+        }
+    }
+
+    struct MissingProvider;
+
+    impl CredentialsProvider for MissingProvider {
+        fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+            Err(error::Error::CredentialsMissing(
+                "not configured".to_string(),
+            ))
+        }
+    }
+
+    struct BrokenProvider;
+
+    impl CredentialsProvider for BrokenProvider {
+        fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+            Err(error::Error::io(
+                "unreachable",
+                io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused"),
+            ))
+        }
+    }
+
+    fn counting(expiration: Option<DateTime<Utc>>) -> (Arc<AtomicUsize>, Box<CountingProvider>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider = Box::new(CountingProvider {
+            calls: calls.clone(),
+            expiration,
+        });
+        (calls, provider)
+    }
+
+    #[test]
+    fn test_chain() {
+        let (_, provider) = counting(None);
+        let chain = ChainProvider::new(vec![Box::new(MissingProvider), provider]);
+        assert_eq!("AKID0", chain.credentials().unwrap().access_key_id);
+
+        let (calls, provider) = counting(None);
+        let chain = ChainProvider::new(vec![Box::new(BrokenProvider), provider]);
+        assert!(matches!(chain.credentials(), Err(error::Error::Io { .. })));
+        assert_eq!(0, calls.load(Ordering::SeqCst));
+
+        let chain = ChainProvider::new(vec![Box::new(MissingProvider)]);
+        assert!(matches!(
+            chain.credentials(),
+            Err(error::Error::CredentialsMissing(_))
+        ));
+    }
+
+    #[test]
+    fn test_default_chain() {
+        assert_eq!(5, ChainProvider::default_chain().providers.len());
+        let client: Arc<dyn HttpClient> = Arc::new(TcpHttpClient::default());
+        assert_eq!(
+            5,
+            ChainProvider::default_chain_with_http_client(
+                client,
+                Arc::new(SkewCorrectedClock::new())
+            )
+            .providers
+            .len()
+        );
+    }
+
+    #[test]
+    fn test_web_identity_without_client() {
+        let imds = test_server::start(|_| (200, String::new()));
+        let web_identity = WebIdentityProvider::with_settings(WebIdentitySettings {
+            token_file: "/var/run/secrets/eks.amazonaws.com/serviceaccount/token".into(),
+            role_arn: "arn:aws:iam::123456789012:role/pod".to_string(),
+            role_session_name: None,
+        });
+        let chain = ChainProvider::new(vec![
+            Box::new(MissingProvider),
+            Box::new(web_identity),
+            Box::new(ImdsProvider::with_endpoint(&imds.endpoint)),
+        ]);
+        // The pod's role is configured, so the chain must not fall through to the node's role
+        assert!(matches!(
+            chain.credentials(),
+            Err(error::Error::HttpClientRequired(_))
+        ));
+        assert!(imds.requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_caching() {
+        let expiration = Utc::now() + chrono::Duration::hours(1);
+        let (calls, provider) = counting(Some(expiration));
+        let cache = CachingProvider::new(provider, DEFAULT_REFRESH_WINDOW);
+        assert_eq!("AKID0", cache.credentials().unwrap().access_key_id);
+        assert_eq!("AKID0", cache.credentials().unwrap().access_key_id);
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+
+        cache.invalidate();
+        assert_eq!("AKID1", cache.credentials().unwrap().access_key_id);
+    }
+
+    #[test]
+    fn test_refresh_before_expiry() {
+        let expiration = Utc::now() + chrono::Duration::minutes(2);
+        let (calls, provider) = counting(Some(expiration));
+        let cache = CachingProvider::new(provider, DEFAULT_REFRESH_WINDOW);
+        assert_eq!("AKID0", cache.credentials().unwrap().access_key_id);
+        assert_eq!("AKID1", cache.credentials().unwrap().access_key_id);
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+
+        let (calls, provider) = counting(Some(expiration));
+        let cache = CachingProvider::new(provider, Duration::from_secs(60));
+        cache.credentials().unwrap();
+        cache.credentials().unwrap();
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+
+        let (calls, provider) = counting(Some(expiration));
+        let cache = CachingProvider::new(provider, Duration::MAX);
+        cache.credentials().unwrap();
+        cache.credentials().unwrap();
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_clock() {
+        let now = Utc.ymd_opt(2015, 8, 30).and_hms_opt(12, 0, 0).unwrap();
+        let (calls, provider) = counting(Some(now + chrono::Duration::minutes(10)));
+        let clock = Arc::new(SkewCorrectedClock::with_clock(Box::new(FixedClock(now))));
+        let cache = CachingProvider::new(provider, DEFAULT_REFRESH_WINDOW)
+            .with_clock(Box::new(clock.clone()));
+        cache.credentials().unwrap();
+        cache.credentials().unwrap();
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+
+        clock.set_offset(chrono::Duration::minutes(6));
+        cache.credentials().unwrap();
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+    }
+
+    struct FailAfterFirstProvider {
+        calls: AtomicUsize,
+        expiration: DateTime<Utc>,
+    }
+
+    impl CredentialsProvider for FailAfterFirstProvider {
+        fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) > 0 {
+                return BrokenProvider.credentials();
+            }
+            Ok(SigningCredentials {
+                access_key_id: "AKID".to_string(),
+                secret_access_key: "secret".to_string(),
+                session_token: None,
+                expiration: Some(self.expiration),
+            })
+        }
+    }
+
+    #[test]
+    fn test_failed_refresh() {
+        let now = Utc.ymd_opt(2015, 8, 30).and_hms_opt(12, 0, 0).unwrap();
+        let provider = Box::new(FailAfterFirstProvider {
+            calls: AtomicUsize::new(0),
+            expiration: now + chrono::Duration::minutes(10),
+        });
+        let clock = Arc::new(SkewCorrectedClock::with_clock(Box::new(FixedClock(now))));
+        let cache = CachingProvider::new(provider, DEFAULT_REFRESH_WINDOW)
+            .with_clock(Box::new(clock.clone()));
+        assert_eq!("AKID", cache.credentials().unwrap().access_key_id);
+
+        // Within the refresh window, a failed refresh falls back to the cached credentials
+        clock.set_offset(chrono::Duration::minutes(6));
+        assert_eq!("AKID", cache.credentials().unwrap().access_key_id);
+
+        clock.set_offset(chrono::Duration::minutes(10));
+        assert!(matches!(cache.credentials(), Err(error::Error::Io { .. })));
+    }
+}
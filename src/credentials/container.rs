@@ -0,0 +1,282 @@
+use std::env;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use url::{Host, Url};
+
+use crate::credentials::{credentials_from_json, CredentialsProvider};
+use crate::error;
+use crate::http::{HttpClient, HttpRequest, TcpHttpClient};
+use crate::presigner::SigningCredentials;
+
+const ECS_ENDPOINT: &str = "http://169.254.170.2";
+/// The ECS agent and the EKS Pod Identity agent (IPv4 and IPv6).
+const ALLOWED_HTTP_HOSTS: [&str; 3] = ["169.254.170.2", "169.254.170.23", "fd00:ec2::23"];
+
+/// How to authorize against the container credentials endpoint. A token file is re-read on every
+/// request since the token may be rotated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContainerAuthorization {
+    Token(String),
+    TokenFile(PathBuf),
+}
+
+/// Fetches credentials from the ECS (or EKS Pod Identity) container credentials endpoint.
+///
+/// The endpoint is configured by `AWS_CONTAINER_CREDENTIALS_RELATIVE_URI` (relative to the ECS
+/// agent address) or `AWS_CONTAINER_CREDENTIALS_FULL_URI`, with an optional authorization token
+/// from `AWS_CONTAINER_AUTHORIZATION_TOKEN_FILE` or `AWS_CONTAINER_AUTHORIZATION_TOKEN`.
+pub struct ContainerProvider {
+    endpoint: Option<String>,
+    authorization: Option<ContainerAuthorization>,
+    client: Box<dyn HttpClient>,
+    // The built-in client only speaks plain HTTP
+    https_supported: bool,
+}
+
+impl ContainerProvider {
+    pub fn new() -> ContainerProvider {
+        let (endpoint, authorization) = settings_from(|name| env::var(name).ok());
+        ContainerProvider {
+            endpoint,
+            authorization,
+            client: Box::new(TcpHttpClient::default()),
+            https_supported: false,
+        }
+    }
+
+    pub fn with_endpoint(
+        endpoint: &str,
+        authorization: Option<ContainerAuthorization>,
+    ) -> ContainerProvider {
+        ContainerProvider {
+            https_supported: false,
+            ..ContainerProvider::with_http_client(
+                endpoint,
+                authorization,
+                Box::new(TcpHttpClient::default()),
+            )
+        }
+    }
+
+    /// Use `client` for requests, which must support HTTPS if the endpoint is an `https` URI.
+    pub fn with_http_client(
+        endpoint: &str,
+        authorization: Option<ContainerAuthorization>,
+        client: Box<dyn HttpClient>,
+    ) -> ContainerProvider {
+        ContainerProvider {
+            endpoint: Some(endpoint.to_string()),
+            authorization,
+            client,
+            https_supported: true,
+        }
+    }
+
+    fn authorization_token(&self) -> Result<Option<String>, error::Error> {
+        match &self.authorization {
+            None => Ok(None),
+            Some(ContainerAuthorization::Token(token)) => Ok(Some(token.clone())),
+            Some(ContainerAuthorization::TokenFile(path)) => fs::read_to_string(path)
+                .map(|token| Some(token.trim().to_string()))
+                .map_err(|e| error::Error::io(&format!("unable to read {}", path.display()), e)),
+        }
+    }
+}
+
+impl Default for ContainerProvider {
+    fn default() -> ContainerProvider {
+        ContainerProvider::new()
+    }
+}
+
+impl CredentialsProvider for ContainerProvider {
+    fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+        let endpoint = self.endpoint.as_ref().ok_or_else(|| {
+            error::Error::CredentialsMissing("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI and AWS_CONTAINER_CREDENTIALS_FULL_URI are not set".to_string())
+        })?;
+        let url = validate_endpoint(endpoint, self.https_supported)?;
+
+        let mut request = HttpRequest::new("GET", url).header("Accept", "application/json");
+        if let Some(token) = self.authorization_token()? {
+            request = request.header("Authorization", &token);
+        }
+
+        let response = self.client.send(&request)?;
+        if !response.is_success() {
+            return Err(error::Error::Http {
+                message: format!(
+                    "container credentials request failed with status {}",
+                    response.status
+                ),
+                status: Some(response.status),
+            });
+        }
+        credentials_from_json(&response.body_string(), "Token")
+    }
+}
+
+fn settings_from<F>(lookup: F) -> (Option<String>, Option<ContainerAuthorization>)
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let lookup = |name: &str| lookup(name).filter(|v| !v.is_empty());
+
+    let endpoint = match lookup("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI") {
+        Some(relative) => Some(format!("{}{}", ECS_ENDPOINT, relative)),
+        None => lookup("AWS_CONTAINER_CREDENTIALS_FULL_URI"),
+    };
+    let authorization = match lookup("AWS_CONTAINER_AUTHORIZATION_TOKEN_FILE") {
+        Some(path) => Some(ContainerAuthorization::TokenFile(PathBuf::from(path))),
+        None => lookup("AWS_CONTAINER_AUTHORIZATION_TOKEN").map(ContainerAuthorization::Token),
+    };
+    (endpoint, authorization)
+}
+
+/// Plain HTTP is only allowed to loopback addresses and the well-known ECS/EKS agent addresses,
+/// so that credentials are never fetched in the clear from an arbitrary host. HTTPS is only
+/// allowed if the client supports it.
+fn validate_endpoint(endpoint: &str, https_supported: bool) -> Result<Url, error::Error> {
+    let invalid = |message: &str| error::Error::InvalidEndpoint {
+        endpoint: endpoint.to_string(),
+        message: message.to_string(),
+        source: None,
+    };
+    let url = Url::parse(endpoint).map_err(|e| error::Error::InvalidEndpoint {
+        endpoint: endpoint.to_string(),
+        message: "invalid container credentials URI".to_string(),
+        source: Some(e),
+    })?;
+    match url.scheme() {
+        "https" if https_supported => Ok(url),
+        "https" => Err(invalid(
+            "https URIs need an HTTPS-capable client (see ContainerProvider::with_http_client)",
+        )),
+        "http" => {
+            let allowed = match url.host() {
+                Some(Host::Ipv4(ip)) => {
+                    IpAddr::V4(ip).is_loopback()
+                        || ALLOWED_HTTP_HOSTS.contains(&ip.to_string().as_str())
+                }
+                Some(Host::Ipv6(ip)) => {
+                    IpAddr::V6(ip).is_loopback()
+                        || ALLOWED_HTTP_HOSTS.contains(&ip.to_string().as_str())
+                }
+                Some(Host::Domain(domain)) => domain == "localhost",
+                None => false,
+            };
+            if allowed {
+                Ok(url)
+            } else {
+                Err(invalid(
+                    "plain HTTP is only allowed to loopback or container agent addresses",
+                ))
+            }
+        }
+        scheme => Err(invalid(&format!("unsupported scheme {}", scheme))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::credentials::container::*;
+    use crate::http::test_server;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_settings() {
+        let (endpoint, authorization) = settings_from(lookup(&[
+            (
+                "AWS_CONTAINER_CREDENTIALS_RELATIVE_URI",
+                "/v2/credentials/abc",
+            ),
+            (
+                "AWS_CONTAINER_CREDENTIALS_FULL_URI",
+                "http://127.0.0.1/ignored",
+            ),
+            ("AWS_CONTAINER_AUTHORIZATION_TOKEN", "token"),
+        ]));
+        assert_eq!(
+            Some("http://169.254.170.2/v2/credentials/abc".to_string()),
+            endpoint
+        );
+        assert_eq!(
+            Some(ContainerAuthorization::Token("token".to_string())),
+            authorization
+        );
+
+        let (endpoint, authorization) = settings_from(lookup(&[
+            (
+                "AWS_CONTAINER_CREDENTIALS_FULL_URI",
+                "http://127.0.0.1/creds",
+            ),
+            ("AWS_CONTAINER_AUTHORIZATION_TOKEN", "token"),
+            ("AWS_CONTAINER_AUTHORIZATION_TOKEN_FILE", "/var/token"),
+        ]));
+        assert_eq!(Some("http://127.0.0.1/creds".to_string()), endpoint);
+        assert_eq!(
+            Some(ContainerAuthorization::TokenFile(PathBuf::from(
+                "/var/token"
+            ))),
+            authorization
+        );
+    }
+
+    #[test]
+    fn test_validate_endpoint() {
+        assert!(validate_endpoint("http://169.254.170.2/creds", false).is_ok());
+        assert!(validate_endpoint("http://169.254.170.23/v1/credentials", false).is_ok());
+        assert!(validate_endpoint("http://[fd00:ec2::23]/v1/credentials", false).is_ok());
+        assert!(validate_endpoint("http://localhost:8080/creds", false).is_ok());
+        assert!(validate_endpoint("http://[::1]/creds", false).is_ok());
+        assert!(validate_endpoint("https://example.com/creds", true).is_ok());
+        assert!(validate_endpoint("https://example.com/creds", false).is_err());
+        assert!(validate_endpoint("http://example.com/creds", true).is_err());
+        assert!(validate_endpoint("http://[fd00:ec2::24]/creds", false).is_err());
+        assert!(validate_endpoint("ftp://127.0.0.1/creds", true).is_err());
+    }
+
+    #[test]
+    fn test_credentials() {
+        let server = test_server::start(|request| {
+            if request.path == "/creds" && request.header("Authorization") == Some("secret-token") {
+                (
+                    200,
+                    r#"{"AccessKeyId":"ASIAEXAMPLE","SecretAccessKey":"secret","Token":"token","Expiration":"2020-01-01T06:00:00Z","RoleArn":"arn"}"#
+                        .to_string(),
+                )
+            } else {
+                (403, String::new())
+            }
+        });
+
+        let provider = ContainerProvider::with_endpoint(
+            &format!("{}/creds", server.endpoint),
+            Some(ContainerAuthorization::Token("secret-token".to_string())),
+        );
+        let credentials = provider.credentials().unwrap();
+        assert_eq!("ASIAEXAMPLE", credentials.access_key_id);
+        assert_eq!(Some("token".to_string()), credentials.session_token);
+        assert!(credentials.expiration.is_some());
+
+        let unauthorized =
+            ContainerProvider::with_endpoint(&format!("{}/creds", server.endpoint), None);
+        assert!(matches!(
+            unauthorized.credentials(),
+            Err(error::Error::Http {
+                status: Some(403),
+                ..
+            })
+        ));
+    }
+}
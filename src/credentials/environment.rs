@@ -0,0 +1,113 @@
+use std::env;
+
+use crate::credentials::CredentialsProvider;
+use crate::error;
+use crate::presigner::SigningCredentials;
+
+const ACCESS_KEY_ID_VARS: [&str; 2] = ["AWS_ACCESS_KEY_ID", "AWS_ACCESS_KEY"];
+const SECRET_ACCESS_KEY_VARS: [&str; 2] = ["AWS_SECRET_ACCESS_KEY", "AWS_SECRET_KEY"];
+const SESSION_TOKEN_VAR: &str = "AWS_SESSION_TOKEN";
+
+/// Reads credentials from the standard `AWS_*` environment variables.
+///
+/// The legacy `AWS_ACCESS_KEY` and `AWS_SECRET_KEY` names are used as fallbacks.
+#[derive(Debug, Default, Clone)]
+pub struct EnvironmentProvider;
+
+impl EnvironmentProvider {
+    pub fn new() -> EnvironmentProvider {
+        EnvironmentProvider
+    }
+}
+
+impl CredentialsProvider for EnvironmentProvider {
+    fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+        credentials_from(|name| env::var(name).ok())
+    }
+}
+
+fn credentials_from<F>(lookup: F) -> Result<SigningCredentials, error::Error>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let first_of = |names: &[&str]| {
+        names
+            .iter()
+            .filter_map(|name| lookup(name))
+            .find(|value| !value.is_empty())
+    };
+
+    let access_key_id = first_of(&ACCESS_KEY_ID_VARS);
+    let secret_access_key = first_of(&SECRET_ACCESS_KEY_VARS);
+    let session_token = first_of(&[SESSION_TOKEN_VAR]);
+
+    match (access_key_id, secret_access_key) {
+        (Some(access_key_id), Some(secret_access_key)) => Ok(SigningCredentials {
+            access_key_id,
+            secret_access_key,
+            session_token,
+            expiration: None,
+        }),
+        (Some(_), None) => Err(error::Error::CredentialsIncomplete(
+            "AWS_ACCESS_KEY_ID is set but AWS_SECRET_ACCESS_KEY is not".to_string(),
+        )),
+        (None, Some(_)) => Err(error::Error::CredentialsIncomplete(
+            "AWS_SECRET_ACCESS_KEY is set but AWS_ACCESS_KEY_ID is not".to_string(),
+        )),
+        (None, None) => Err(error::Error::CredentialsMissing(
+            "AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY are not set".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::credentials::environment::credentials_from;
+    use crate::error::Error;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_standard_names() {
+        let credentials = credentials_from(lookup(&[
+            ("AWS_ACCESS_KEY_ID", "AKID"),
+            ("AWS_SECRET_ACCESS_KEY", "secret"),
+            ("AWS_SESSION_TOKEN", "token"),
+        ]))
+        .unwrap();
+        assert_eq!("AKID", credentials.access_key_id);
+        assert_eq!("secret", credentials.secret_access_key);
+        assert_eq!(Some("token".to_string()), credentials.session_token);
+    }
+
+    #[test]
+    fn test_legacy_names() {
+        let credentials = credentials_from(lookup(&[
+            ("AWS_ACCESS_KEY", "AKID"),
+            ("AWS_SECRET_KEY", "secret"),
+        ]))
+        .unwrap();
+        assert_eq!("AKID", credentials.access_key_id);
+        assert_eq!("secret", credentials.secret_access_key);
+        assert_eq!(None, credentials.session_token);
+    }
+
+    #[test]
+    fn test_missing_and_partial() {
+        let missing = credentials_from(lookup(&[])).err().unwrap();
+        assert!(matches!(missing, Error::CredentialsMissing(_)));
+
+        let partial = credentials_from(lookup(&[("AWS_ACCESS_KEY_ID", "AKID")]))
+            .err()
+            .unwrap();
+        assert!(matches!(partial, Error::CredentialsIncomplete(_)));
+    }
+}
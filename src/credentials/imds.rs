@@ -0,0 +1,230 @@
+use std::env;
+use std::time::Duration;
+
+use url::Url;
+
+use crate::credentials::{credentials_from_json, CredentialsProvider};
+use crate::error;
+use crate::http::{HttpClient, HttpRequest, HttpResponse, TcpHttpClient};
+use crate::presigner::SigningCredentials;
+
+const DEFAULT_ENDPOINT: &str = "http://169.254.169.254";
+const TOKEN_PATH: &str = "/latest/api/token";
+const CREDENTIALS_PATH: &str = "/latest/meta-data/iam/security-credentials/";
+const TOKEN_TTL_SECONDS: u32 = 21600;
+/// Off EC2 the metadata service is usually unreachable, so don't wait long to find out.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Fetches temporary credentials for the instance's IAM role from the EC2 instance metadata
+/// service, using the IMDSv2 session token flow.
+///
+/// The endpoint can be overridden with `AWS_EC2_METADATA_SERVICE_ENDPOINT`, and the provider
+/// can be turned off entirely by setting `AWS_EC2_METADATA_DISABLED=true`.
+pub struct ImdsProvider {
+    endpoint: String,
+    disabled: bool,
+    client: Box<dyn HttpClient>,
+}
+
+impl ImdsProvider {
+    pub fn new() -> ImdsProvider {
+        let endpoint = env::var("AWS_EC2_METADATA_SERVICE_ENDPOINT")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string());
+        let disabled = env::var("AWS_EC2_METADATA_DISABLED")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        ImdsProvider {
+            disabled,
+            ..ImdsProvider::with_endpoint(&endpoint)
+        }
+    }
+
+    pub fn with_endpoint(endpoint: &str) -> ImdsProvider {
+        ImdsProvider::with_http_client(
+            endpoint,
+            Box::new(TcpHttpClient::default().with_connect_timeout(CONNECT_TIMEOUT)),
+        )
+    }
+
+    pub fn with_http_client(endpoint: &str, client: Box<dyn HttpClient>) -> ImdsProvider {
+        ImdsProvider {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            disabled: false,
+            client,
+        }
+    }
+
+    fn url(&self, path: &str) -> Result<Url, error::Error> {
+        Url::parse(&format!("{}{}", self.endpoint, path)).map_err(|e| {
+            error::Error::InvalidEndpoint {
+                endpoint: self.endpoint.clone(),
+                message: "invalid instance metadata endpoint".to_string(),
+                source: Some(e),
+            }
+        })
+    }
+
+    fn get(&self, path: &str, token: &str) -> Result<HttpResponse, error::Error> {
+        let request =
+            HttpRequest::new("GET", self.url(path)?).header("X-aws-ec2-metadata-token", token);
+        self.client.send(&request)
+    }
+
+    fn fetch_token(&self) -> Result<String, error::Error> {
+        let request = HttpRequest::new("PUT", self.url(TOKEN_PATH)?).header(
+            "X-aws-ec2-metadata-token-ttl-seconds",
+            &TOKEN_TTL_SECONDS.to_string(),
+        );
+        let response = match self.client.send(&request) {
+            // Failing to reach the service at all means that we're not running on EC2
+            Err(error::Error::Io { source, .. }) => {
+                return Err(error::Error::CredentialsMissing(format!(
+                    "instance metadata service is unreachable: {}",
+                    source
+                )))
+            }
+            response => check_status(response?, "session token")?,
+        };
+        Ok(response.body_string().trim().to_string())
+    }
+}
+
+impl Default for ImdsProvider {
+    fn default() -> ImdsProvider {
+        ImdsProvider::new()
+    }
+}
+
+impl CredentialsProvider for ImdsProvider {
+    fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+        if self.disabled {
+            return Err(error::Error::CredentialsMissing(
+                "instance metadata service is disabled".to_string(),
+            ));
+        }
+
+        let token = self.fetch_token()?;
+
+        let response = self.get(CREDENTIALS_PATH, &token)?;
+        if response.status == 404 {
+            return Err(error::Error::CredentialsMissing(
+                "no IAM role is attached to this instance".to_string(),
+            ));
+        }
+        let body = check_status(response, "role name")?.body_string();
+        let role_name = body
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty())
+            .ok_or_else(|| {
+                error::Error::CredentialsMissing(
+                    "no IAM role is attached to this instance".to_string(),
+                )
+            })?;
+
+        let response = self.get(&format!("{}{}", CREDENTIALS_PATH, role_name), &token)?;
+        let document = check_status(response, "role credentials")?.body_string();
+        credentials_from_json(&document, "Token")
+    }
+}
+
+fn check_status(response: HttpResponse, what: &str) -> Result<HttpResponse, error::Error> {
+    if response.is_success() {
+        Ok(response)
+    } else {
+        Err(error::Error::Http {
+            message: format!(
+                "instance metadata request for {} failed with status {}",
+                what, response.status
+            ),
+            status: Some(response.status),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::credentials::imds::ImdsProvider;
+    use crate::credentials::CredentialsProvider;
+    use crate::error::Error;
+    use crate::http::test_server;
+
+    #[test]
+    fn test_credentials() {
+        let server =
+            test_server::start(
+                |request| match (request.method.as_str(), request.path.as_str()) {
+                    ("PUT", "/latest/api/token") => (200, "session-token".to_string()),
+                    (_, _)
+                        if request.header("X-aws-ec2-metadata-token") != Some("session-token") =>
+                    {
+                        (401, String::new())
+                    }
+                    ("GET", "/latest/meta-data/iam/security-credentials/") => {
+                        (200, "my-role\n".to_string())
+                    }
+                    ("GET", "/latest/meta-data/iam/security-credentials/my-role") => (
+                        200,
+                        r#"{
+                      "Code" : "Success",
+                      "LastUpdated" : "2020-01-01T00:00:00Z",
+                      "Type" : "AWS-HMAC",
+                      "AccessKeyId" : "ASIAEXAMPLE",
+                      "SecretAccessKey" : "secret",
+                      "Token" : "token",
+                      "Expiration" : "2020-01-01T06:00:00Z"
+                    }"#
+                        .to_string(),
+                    ),
+                    _ => (404, String::new()),
+                },
+            );
+
+        let provider = ImdsProvider::with_endpoint(&server.endpoint);
+        let credentials = provider.credentials().unwrap();
+        assert_eq!("ASIAEXAMPLE", credentials.access_key_id);
+        assert_eq!("secret", credentials.secret_access_key);
+        assert_eq!(Some("token".to_string()), credentials.session_token);
+        assert_eq!(
+            Some(Utc.ymd_opt(2020, 1, 1).and_hms_opt(6, 0, 0).unwrap()),
+            credentials.expiration
+        );
+
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(3, requests.len());
+        assert_eq!(
+            Some("21600"),
+            requests[0].header("X-aws-ec2-metadata-token-ttl-seconds")
+        );
+        assert!(requests[0].body.is_empty());
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let provider = ImdsProvider::with_endpoint(&endpoint);
+        let error = provider.credentials().err().unwrap();
+        assert!(matches!(error, Error::CredentialsMissing(_)));
+    }
+
+    #[test]
+    fn test_no_role() {
+        let server = test_server::start(|request| match request.method.as_str() {
+            "PUT" => (200, "session-token".to_string()),
+            _ => (404, String::new()),
+        });
+
+        let provider = ImdsProvider::with_endpoint(&server.endpoint);
+        let error = provider.credentials().err().unwrap();
+        assert!(matches!(error, Error::CredentialsMissing(_)));
+    }
+}
@@ -0,0 +1,129 @@
+use std::process::Command;
+
+use crate::credentials::{credentials_from_value, CredentialsProvider};
+use crate::error;
+use crate::json;
+use crate::presigner::SigningCredentials;
+
+/// Runs an external command (the `credential_process` profile setting) and reads credentials from
+/// the JSON document it writes to standard output.
+///
+/// The command is run through the platform shell (`sh -c` or `cmd /C`).
+#[derive(Debug, Clone)]
+pub struct ProcessProvider {
+    command: String,
+}
+
+impl ProcessProvider {
+    pub fn new(command: &str) -> ProcessProvider {
+        ProcessProvider {
+            command: command.to_string(),
+        }
+    }
+}
+
+impl CredentialsProvider for ProcessProvider {
+    fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+        let output = shell_command(&self.command).output().map_err(|e| {
+            error::Error::io(
+                &format!("unable to run credential_process {}", self.command),
+                e,
+            )
+        })?;
+
+        if !output.status.success() {
+            return Err(error::Error::ProcessFailed {
+                command: self.command.clone(),
+                exit_code: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+
+        parse_output(&String::from_utf8_lossy(&output.stdout)).map_err(|e| {
+            error::Error::ProcessOutput {
+                command: self.command.clone(),
+                source: Box::new(e),
+            }
+        })
+    }
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+fn parse_output(output: &str) -> Result<SigningCredentials, error::Error> {
+    let value = json::parse(output.trim())?;
+    match value.get("Version") {
+        Some(json::Value::Number(version)) if *version == 1.0 => {}
+        Some(version) => {
+            return Err(error::Error::parse(&format!(
+                "unsupported Version {:?}",
+                version
+            )))
+        }
+        None => return Err(error::Error::parse("missing Version")),
+    }
+    credentials_from_value(&value, "SessionToken")
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use crate::credentials::process::ProcessProvider;
+    use crate::credentials::CredentialsProvider;
+    use crate::error::Error;
+
+    #[test]
+    fn test_credentials() {
+        let provider = ProcessProvider::new(
+            r#"echo '{"Version": 1, "AccessKeyId": "AKID", "SecretAccessKey": "secret", "SessionToken": "token", "Expiration": "2020-01-01T06:00:00Z"}'"#,
+        );
+        let credentials = provider.credentials().unwrap();
+        assert_eq!("AKID", credentials.access_key_id);
+        assert_eq!("secret", credentials.secret_access_key);
+        assert_eq!(Some("token".to_string()), credentials.session_token);
+        assert_eq!(
+            Some(Utc.ymd_opt(2020, 1, 1).and_hms_opt(6, 0, 0).unwrap()),
+            credentials.expiration
+        );
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let provider = ProcessProvider::new("echo denied >&2; exit 3");
+        let error = provider.credentials().err().unwrap();
+        match error {
+            Error::ProcessFailed {
+                exit_code, stderr, ..
+            } => {
+                assert_eq!(Some(3), exit_code);
+                assert_eq!("denied", stderr);
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_malformed_output() {
+        for command in &[
+            "echo not json",
+            r#"echo '{"Version": 2, "AccessKeyId": "AKID", "SecretAccessKey": "secret"}'"#,
+            r#"echo '{"Version": 1, "AccessKeyId": "AKID"}'"#,
+        ] {
+            let error = ProcessProvider::new(command).credentials().err().unwrap();
+            assert!(matches!(error, Error::ProcessOutput { .. }));
+        }
+    }
+}
@@ -0,0 +1,666 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::{Clock, SystemClock};
+use crate::credentials::container::ContainerProvider;
+use crate::credentials::environment::EnvironmentProvider;
+use crate::credentials::imds::ImdsProvider;
+use crate::credentials::process::ProcessProvider;
+use crate::credentials::sts::{AssumeRoleOptions, AssumeRoleProvider, MfaTokenProvider};
+use crate::credentials::CredentialsProvider;
+use crate::error;
+use crate::http::HttpClient;
+use crate::presigner::SigningCredentials;
+
+const DEFAULT_PROFILE: &str = "default";
+
+/// A single named profile, merged from the shared config and credentials files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub properties: HashMap<String, String>,
+}
+
+impl Profile {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(|v| v.as_str())
+    }
+
+    pub fn region(&self) -> Option<&str> {
+        self.get("region")
+    }
+
+    /// The static credentials in this profile, if there are any.
+    pub fn static_credentials(&self) -> Result<Option<SigningCredentials>, error::Error> {
+        match (
+            self.get("aws_access_key_id"),
+            self.get("aws_secret_access_key"),
+        ) {
+            (Some(access_key_id), Some(secret_access_key)) => Ok(Some(SigningCredentials {
+                access_key_id: access_key_id.to_string(),
+                secret_access_key: secret_access_key.to_string(),
+                session_token: self.get("aws_session_token").map(|v| v.to_string()),
+                expiration: None,
+            })),
+            (None, None) => Ok(None),
+            _ => Err(error::Error::CredentialsIncomplete(format!(
+                "profile {} must set both aws_access_key_id and aws_secret_access_key",
+                self.name
+            ))),
+        }
+    }
+}
+
+/// All of the profiles defined in a pair of shared config and credentials files.
+#[derive(Debug, Clone, Default)]
+pub struct ProfileSet {
+    profiles: HashMap<String, Profile>,
+}
+
+impl ProfileSet {
+    /// Load profiles from the given files. Files that do not exist are treated as empty.
+    pub fn load(credentials_path: &Path, config_path: &Path) -> Result<ProfileSet, error::Error> {
+        let mut profile_set = ProfileSet::default();
+        // Values from the credentials file take precedence over the config file
+        if let Some(contents) = read_optional(config_path)? {
+            profile_set.merge(&contents, true)?;
+        }
+        if let Some(contents) = read_optional(credentials_path)? {
+            profile_set.merge(&contents, false)?;
+        }
+        Ok(profile_set)
+    }
+
+    /// Parse profiles from the contents of a config file (`is_config == true`, where sections
+    /// are named `[profile x]`) or a credentials file (where sections are named `[x]`).
+    pub fn merge(&mut self, contents: &str, is_config: bool) -> Result<(), error::Error> {
+        for (section, properties) in parse_ini(contents)? {
+            let name = match profile_name(&section, is_config) {
+                Some(name) => name,
+                None => continue,
+            };
+            let profile = self.profiles.entry(name.clone()).or_insert(Profile {
+                name,
+                properties: HashMap::new(),
+            });
+            profile.properties.extend(properties);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+}
+
+/// Reads credentials (and the default region) from a named profile in the shared
+/// `~/.aws/credentials` and `~/.aws/config` files.
+///
+/// The file locations can be overridden with `AWS_SHARED_CREDENTIALS_FILE` and `AWS_CONFIG_FILE`,
+/// and the profile name with `AWS_PROFILE`.
+///
+/// Profiles with a `credential_process` (and no static credentials) run that command to obtain
+/// credentials. Profiles with a `role_arn` assume that role using credentials from their
+/// `source_profile` (which may itself assume a role) or `credential_source`, calling STS with the
+/// client given to `with_http_client`.
+#[derive(Clone)]
+pub struct ProfileProvider {
+    profile_name: String,
+    credentials_path: PathBuf,
+    config_path: PathBuf,
+    sts_endpoint: Option<String>,
+    mfa_token: Option<MfaTokenProvider>,
+    client: Option<Arc<dyn HttpClient>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ProfileProvider {
+    pub fn new() -> ProfileProvider {
+        let profile_name = env::var("AWS_PROFILE")
+            .ok()
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+        ProfileProvider::with_profile(&profile_name)
+    }
+
+    pub fn with_profile(profile_name: &str) -> ProfileProvider {
+        ProfileProvider::with_paths(
+            profile_name,
+            &shared_file_path("AWS_SHARED_CREDENTIALS_FILE", "credentials"),
+            &shared_file_path("AWS_CONFIG_FILE", "config"),
+        )
+    }
+
+    pub fn with_paths(
+        profile_name: &str,
+        credentials_path: &Path,
+        config_path: &Path,
+    ) -> ProfileProvider {
+        ProfileProvider {
+            profile_name: profile_name.to_string(),
+            credentials_path: credentials_path.to_path_buf(),
+            config_path: config_path.to_path_buf(),
+            sts_endpoint: None,
+            mfa_token: None,
+            client: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Override the STS endpoint used for role profiles (which otherwise depends on the region).
+    pub fn with_sts_endpoint(mut self, endpoint: &str) -> ProfileProvider {
+        self.sts_endpoint = Some(endpoint.to_string());
+        self
+    }
+
+    /// Supply MFA token codes for role profiles that set `mfa_serial`.
+    pub fn with_mfa_token_provider(mut self, mfa_token: MfaTokenProvider) -> ProfileProvider {
+        self.mfa_token = Some(mfa_token);
+        self
+    }
+
+    /// The client for calling STS in role profiles, which must support HTTPS. Role profiles are
+    /// an error without one.
+    pub fn with_http_client(mut self, client: Arc<dyn HttpClient>) -> ProfileProvider {
+        self.client = Some(client);
+        self
+    }
+
+    /// Sign STS requests for role profiles with the time from `clock`.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> ProfileProvider {
+        self.clock = clock;
+        self
+    }
+
+    pub fn profile_name(&self) -> &str {
+        &self.profile_name
+    }
+
+    pub fn load_profiles(&self) -> Result<ProfileSet, error::Error> {
+        ProfileSet::load(&self.credentials_path, &self.config_path)
+    }
+
+    pub fn profile(&self) -> Result<Profile, error::Error> {
+        self.load_profiles()?
+            .get(&self.profile_name)
+            .cloned()
+            .ok_or_else(|| {
+                error::Error::CredentialsMissing(format!("profile {} not found", self.profile_name))
+            })
+    }
+
+    /// The default region configured for the profile, if any.
+    pub fn region(&self) -> Result<Option<String>, error::Error> {
+        Ok(self
+            .load_profiles()?
+            .get(&self.profile_name)
+            .and_then(|profile| profile.region())
+            .map(|region| region.to_string()))
+    }
+}
+
+impl Default for ProfileProvider {
+    fn default() -> ProfileProvider {
+        ProfileProvider::new()
+    }
+}
+
+impl CredentialsProvider for ProfileProvider {
+    fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+        let profiles = self.load_profiles()?;
+        self.resolve_credentials(&profiles, &self.profile_name, &mut vec![])
+    }
+}
+
+impl ProfileProvider {
+    fn resolve_credentials(
+        &self,
+        profiles: &ProfileSet,
+        name: &str,
+        visited: &mut Vec<String>,
+    ) -> Result<SigningCredentials, error::Error> {
+        let profile = profiles.get(name).ok_or_else(|| {
+            error::Error::CredentialsMissing(format!("profile {} not found", name))
+        })?;
+
+        // As in the AWS SDKs, static credentials take precedence over credential_process
+        let role_arn = match profile.get("role_arn") {
+            Some(role_arn) => role_arn,
+            None => {
+                if let Some(credentials) = profile.static_credentials()? {
+                    return Ok(credentials);
+                }
+                return match profile.get("credential_process") {
+                    Some(command) => ProcessProvider::new(command).credentials(),
+                    None => Err(error::Error::CredentialsMissing(format!(
+                        "profile {} does not contain credentials",
+                        name
+                    ))),
+                };
+            }
+        };
+
+        if visited.iter().any(|v| v == name) {
+            return Err(error::Error::InvalidProfile {
+                profile: name.to_string(),
+                message: format!(
+                    "source_profile cycle detected: {} -> {}",
+                    visited.join(" -> "),
+                    name
+                ),
+            });
+        }
+        visited.push(name.to_string());
+
+        let source: Box<dyn CredentialsProvider> = match (
+            profile.get("source_profile"),
+            profile.get("credential_source"),
+        ) {
+            (Some(source_profile), None) => {
+                // A profile may use its own static credentials as the source for its role
+                let source_credentials = match profile.static_credentials()? {
+                    Some(credentials) if source_profile == name => credentials,
+                    _ => self.resolve_credentials(profiles, source_profile, visited)?,
+                };
+                Box::new(source_credentials)
+            }
+            (None, Some("Environment")) => Box::new(EnvironmentProvider::new()),
+            (None, Some("Ec2InstanceMetadata")) => Box::new(ImdsProvider::new()),
+            (None, Some("EcsContainer")) => Box::new(ContainerProvider::new()),
+            (None, Some(other)) => {
+                return Err(error::Error::InvalidProfile {
+                    profile: name.to_string(),
+                    message: format!("unsupported credential_source {}", other),
+                })
+            }
+            _ => {
+                return Err(error::Error::InvalidProfile {
+                    profile: name.to_string(),
+                    message: "must set exactly one of source_profile and credential_source"
+                        .to_string(),
+                })
+            }
+        };
+
+        let duration = match profile.get("duration_seconds") {
+            Some(seconds) => Some(Duration::from_secs(seconds.parse::<u64>().map_err(
+                |_| error::Error::InvalidProfile {
+                    profile: name.to_string(),
+                    message: format!("invalid duration_seconds {}", seconds),
+                },
+            )?)),
+            None => None,
+        };
+        let options = AssumeRoleOptions {
+            role_session_name: profile.get("role_session_name").map(|v| v.to_string()),
+            external_id: profile.get("external_id").map(|v| v.to_string()),
+            duration,
+            mfa_serial: profile.get("mfa_serial").map(|v| v.to_string()),
+            mfa_token: self.mfa_token.clone(),
+            policy: None,
+        };
+
+        let client = self
+            .client
+            .clone()
+            .ok_or_else(|| error::Error::InvalidProfile {
+                profile: name.to_string(),
+                message: "assuming a role needs an HttpClient that can reach STS \
+                          (see ProfileProvider::with_http_client)"
+                    .to_string(),
+            })?;
+
+        let mut provider = AssumeRoleProvider::new(role_arn, options, source, Box::new(client))
+            .with_clock(Box::new(self.clock.clone()));
+        if let Some(region) = profile.region() {
+            provider = provider.with_region(region);
+        }
+        if let Some(endpoint) = &self.sts_endpoint {
+            provider = provider.with_endpoint(endpoint);
+        }
+        provider.credentials()
+    }
+}
+
+fn shared_file_path(env_var: &str, file_name: &str) -> PathBuf {
+    match env::var(env_var) {
+        Ok(path) if !path.is_empty() => expand_home(&path),
+        _ => home_dir().join(".aws").join(file_name),
+    }
+}
+
+fn home_dir() -> PathBuf {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_default()
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    if path == "~" {
+        home_dir()
+    } else if let Some(rest) = path.strip_prefix("~/").or_else(|| path.strip_prefix("~\\")) {
+        home_dir().join(rest)
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>, error::Error> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(error::Error::io(
+            &format!("unable to read {}", path.display()),
+            e,
+        )),
+    }
+}
+
+/// Map a section header to a profile name, following the AWS CLI rules: in the config file,
+/// named profiles must be written as `[profile name]` (only `default` may omit the prefix),
+/// while in the credentials file the prefix is never used.
+fn profile_name(section: &str, is_config: bool) -> Option<String> {
+    if !is_config {
+        return Some(section.to_string());
+    }
+    if section == DEFAULT_PROFILE {
+        return Some(section.to_string());
+    }
+    let mut parts = section.splitn(2, char::is_whitespace);
+    match (parts.next(), parts.next()) {
+        (Some("profile"), Some(name)) if !name.trim().is_empty() => Some(name.trim().to_string()),
+        _ => None,
+    }
+}
+
+type Section = (String, HashMap<String, String>);
+
+fn parse_ini(contents: &str) -> Result<Vec<Section>, error::Error> {
+    let mut sections: Vec<Section> = vec![];
+    let mut in_sub_section = false;
+
+    for (line_number, raw_line) in contents.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            let name = strip_comment(line)
+                .strip_prefix('[')
+                .and_then(|l| l.strip_suffix(']'))
+                .ok_or_else(|| parse_error(line_number, "invalid section header"))?;
+            sections.push((name.trim().to_string(), HashMap::new()));
+            in_sub_section = false;
+            continue;
+        }
+
+        let (_, properties) = sections
+            .last_mut()
+            .ok_or_else(|| parse_error(line_number, "property outside of a section"))?;
+
+        // Indented lines belong to a nested sub-section (e.g. `s3 =`), which we don't support
+        if raw_line.starts_with(char::is_whitespace) && in_sub_section {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, '=');
+        let key = parts.next().unwrap_or("").trim();
+        let value = parts
+            .next()
+            .ok_or_else(|| parse_error(line_number, "expected key = value"))?;
+        let value = strip_comment(value.trim());
+        if key.is_empty() {
+            return Err(parse_error(line_number, "empty property name"));
+        }
+        in_sub_section = value.is_empty();
+        if !in_sub_section {
+            properties.insert(key.to_lowercase(), value.to_string());
+        }
+    }
+
+    Ok(sections)
+}
+
+/// Strip an inline comment, which must be preceded by whitespace.
+fn strip_comment(value: &str) -> &str {
+    let bytes = value.as_bytes();
+    for i in 1..bytes.len() {
+        if (bytes[i] == b'#' || bytes[i] == b';') && bytes[i - 1].is_ascii_whitespace() {
+            return value[..i].trim_end();
+        }
+    }
+    value
+}
+
+fn parse_error(line_number: usize, message: &str) -> error::Error {
+    error::Error::parse(&format!("line {}: {}", line_number + 1, message))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::clock::FixedClock;
+    use crate::credentials::profile::{ProfileProvider, ProfileSet};
+    use crate::error::Error;
+    use crate::http::{test_server, TcpHttpClient};
+
+    const CONFIG: &str = "
+[default]
+region = us-east-1
+
+[profile dev]
+region = eu-west-1 # Ireland
+s3 =
+    addressing_style = path
+
+[ignored]
+region = us-west-2
+";
+
+    const CREDENTIALS: &str = "
+; comment
+[default]
+aws_access_key_id = AKIDDEFAULT
+aws_secret_access_key = secret/default
+
+[dev]
+aws_access_key_id=AKIDDEV
+aws_secret_access_key=secret-dev
+aws_session_token=token-dev
+
+[profile literal]
+aws_access_key_id = AKIDLITERAL
+";
+
+    fn profiles() -> ProfileSet {
+        let mut profiles = ProfileSet::default();
+        profiles.merge(CONFIG, true).unwrap();
+        profiles.merge(CREDENTIALS, false).unwrap();
+        profiles
+    }
+
+    #[test]
+    fn test_section_rules() {
+        let profiles = profiles();
+        assert!(profiles.get("ignored").is_none());
+        assert!(profiles.get("profile literal").is_some());
+        assert!(profiles.get("literal").is_none());
+    }
+
+    #[test]
+    fn test_merged_profile() {
+        let profiles = profiles();
+
+        let default = profiles.get("default").unwrap();
+        assert_eq!(Some("us-east-1"), default.region());
+        let credentials = default.static_credentials().unwrap().unwrap();
+        assert_eq!("AKIDDEFAULT", credentials.access_key_id);
+        assert_eq!("secret/default", credentials.secret_access_key);
+        assert_eq!(None, credentials.session_token);
+
+        let dev = profiles.get("dev").unwrap();
+        assert_eq!(Some("eu-west-1"), dev.region());
+        assert_eq!(None, dev.get("addressing_style"));
+        let credentials = dev.static_credentials().unwrap().unwrap();
+        assert_eq!("AKIDDEV", credentials.access_key_id);
+        assert_eq!(Some("token-dev".to_string()), credentials.session_token);
+    }
+
+    #[test]
+    fn test_incomplete_credentials() {
+        let profiles = profiles();
+        assert!(profiles
+            .get("profile literal")
+            .unwrap()
+            .static_credentials()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let mut profiles = ProfileSet::default();
+        assert!(profiles.merge("key = value", false).is_err());
+        assert!(profiles.merge("[default]\nnot a property", false).is_err());
+    }
+
+    const ROLE_CONFIG: &str = "
+[profile base]
+aws_access_key_id = AKIDBASE
+aws_secret_access_key = secret
+
+[profile first]
+role_arn = arn:aws:iam::111111111111:role/first
+source_profile = base
+
+[profile second]
+role_arn = arn:aws:iam::222222222222:role/second
+source_profile = first
+external_id = ext
+role_session_name = session
+
+[profile loop-a]
+role_arn = arn:aws:iam::1:role/a
+source_profile = loop-b
+
+[profile loop-b]
+role_arn = arn:aws:iam::1:role/b
+source_profile = loop-a
+";
+
+    #[test]
+    fn test_role_chaining() {
+        let server = test_server::start(|request| {
+            let authorization = request.header("Authorization").unwrap_or("");
+            let key = if authorization.contains("Credential=AKIDBASE/") {
+                "ASIAFIRST"
+            } else if authorization.contains("Credential=ASIAFIRST/") {
+                "ASIASECOND"
+            } else {
+                return (403, String::new());
+            };
+            (
+                200,
+                format!(
+                    "<AssumeRoleResponse><AssumeRoleResult><Credentials>\
+                     <AccessKeyId>{}</AccessKeyId><SecretAccessKey>secret</SecretAccessKey>\
+                     <SessionToken>token</SessionToken><Expiration>2020-01-01T06:00:00Z</Expiration>\
+                     </Credentials></AssumeRoleResult></AssumeRoleResponse>",
+                    key
+                ),
+            )
+        });
+
+        let mut profiles = ProfileSet::default();
+        profiles.merge(ROLE_CONFIG, true).unwrap();
+        let provider = ProfileProvider::with_profile("second")
+            .with_sts_endpoint(&server.endpoint)
+            .with_http_client(Arc::new(TcpHttpClient::default()))
+            .with_clock(Arc::new(FixedClock(
+                Utc.ymd_opt(2015, 8, 30).and_hms_opt(12, 36, 0).unwrap(),
+            )));
+        let credentials = provider
+            .resolve_credentials(&profiles, "second", &mut vec![])
+            .unwrap();
+        assert_eq!("ASIASECOND", credentials.access_key_id);
+
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(2, requests.len());
+        assert!(requests[1].body.contains("&ExternalId=ext"));
+        assert!(requests[1].body.contains("&RoleSessionName=session"));
+        assert_eq!(Some("20150830T123600Z"), requests[1].header("X-Amz-Date"));
+
+        let error = ProfileProvider::with_profile("second")
+            .resolve_credentials(&profiles, "second", &mut vec![])
+            .err()
+            .unwrap();
+        assert!(matches!(error, Error::InvalidProfile { .. }));
+    }
+
+    #[test]
+    fn test_role_cycle() {
+        let mut profiles = ProfileSet::default();
+        profiles.merge(ROLE_CONFIG, true).unwrap();
+        let provider = ProfileProvider::with_profile("loop-a")
+            .with_http_client(Arc::new(TcpHttpClient::default()));
+        let error = provider
+            .resolve_credentials(&profiles, "loop-a", &mut vec![])
+            .err()
+            .unwrap();
+        match error {
+            Error::InvalidProfile { profile, message } => {
+                assert_eq!("loop-a", profile);
+                assert_eq!(
+                    "source_profile cycle detected: loop-a -> loop-b -> loop-a",
+                    message
+                );
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_credential_process() {
+        let mut profiles = ProfileSet::default();
+        profiles
+            .merge(
+                "[profile helper]\ncredential_process = printf '{\"Version\":1,\"AccessKeyId\":\"AKIDPROC\",\"SecretAccessKey\":\"s\"}'\n",
+                true,
+            )
+            .unwrap();
+        let provider = ProfileProvider::with_profile("helper");
+        let credentials = provider
+            .resolve_credentials(&profiles, "helper", &mut vec![])
+            .unwrap();
+        assert_eq!("AKIDPROC", credentials.access_key_id);
+    }
+
+    #[test]
+    fn test_static_credentials_before_process() {
+        let mut profiles = ProfileSet::default();
+        profiles
+            .merge(
+                "[profile both]\n\
+                 aws_access_key_id = AKIDSTATIC\n\
+                 aws_secret_access_key = secret\n\
+                 credential_process = false\n",
+                true,
+            )
+            .unwrap();
+        let provider = ProfileProvider::with_profile("both");
+        let credentials = provider
+            .resolve_credentials(&profiles, "both", &mut vec![])
+            .unwrap();
+        assert_eq!("AKIDSTATIC", credentials.access_key_id);
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use url::Url;
+
+use crate::clock::{Clock, SystemClock};
+use crate::credentials::{parse_expiration, CredentialsProvider};
+use crate::error;
+use crate::http::{HttpClient, HttpRequest};
+use crate::presigner::{self, Payload, PresignerRequest, SigningCredentials, SigningParams};
+use crate::service_error::ServiceError;
+use crate::util::urlencode_param;
+use crate::xml;
+
+pub(crate) const STS_VERSION: &str = "2011-06-15";
+
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// Produces a one-time MFA token code for the given MFA device serial number.
+pub type MfaTokenProvider = Arc<dyn Fn(&str) -> Result<String, error::Error> + Send + Sync>;
+
+/// Optional parameters for an STS `AssumeRole` call.
+#[derive(Clone, Default)]
+pub struct AssumeRoleOptions {
+    pub role_session_name: Option<String>,
+    pub external_id: Option<String>,
+    pub duration: Option<Duration>,
+    pub mfa_serial: Option<String>,
+    pub mfa_token: Option<MfaTokenProvider>,
+    pub policy: Option<String>,
+}
+
+/// Assumes an IAM role with STS `AssumeRole`, signing the call with credentials from another
+/// provider (which may itself be an `AssumeRoleProvider`, for role chaining).
+pub struct AssumeRoleProvider {
+    role_arn: String,
+    options: AssumeRoleOptions,
+    source: Box<dyn CredentialsProvider>,
+    region: String,
+    endpoint: String,
+    /// Whether `endpoint` was set explicitly (or with `AWS_ENDPOINT_URL_STS`), so that
+    /// `with_region` leaves it alone.
+    endpoint_overridden: bool,
+    client: Box<dyn HttpClient>,
+    clock: Box<dyn Clock>,
+}
+
+impl AssumeRoleProvider {
+    /// `client` is used to call STS, so it must support HTTPS.
+    pub fn new(
+        role_arn: &str,
+        options: AssumeRoleOptions,
+        source: Box<dyn CredentialsProvider>,
+        client: Box<dyn HttpClient>,
+    ) -> AssumeRoleProvider {
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .ok()
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| DEFAULT_REGION.to_string());
+        AssumeRoleProvider {
+            role_arn: role_arn.to_string(),
+            options,
+            source,
+            region,
+            endpoint: default_endpoint(),
+            endpoint_overridden: endpoint_override().is_some(),
+            client,
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    /// Sign for `region`, and use its regional STS endpoint unless the endpoint has been
+    /// overridden (by `with_endpoint` or `AWS_ENDPOINT_URL_STS`).
+    pub fn with_region(mut self, region: &str) -> AssumeRoleProvider {
+        self.region = region.to_string();
+        if !self.endpoint_overridden {
+            self.endpoint = regional_endpoint(region);
+        }
+        self
+    }
+
+    pub fn with_endpoint(mut self, endpoint: &str) -> AssumeRoleProvider {
+        self.endpoint = endpoint.to_string();
+        self.endpoint_overridden = true;
+        self
+    }
+
+    /// Sign `AssumeRole` requests with the time from `clock`.
+    pub fn with_clock(mut self, clock: Box<dyn Clock>) -> AssumeRoleProvider {
+        self.clock = clock;
+        self
+    }
+
+    fn build_form(&self) -> Result<String, error::Error> {
+        let role_session_name =
+            self.options.role_session_name.clone().unwrap_or_else(|| {
+                format!("aws-presigner-{}", self.clock.now().timestamp_millis())
+            });
+        let duration = self.options.duration.map(|d| d.as_secs().to_string());
+        let token_code = match (&self.options.mfa_serial, &self.options.mfa_token) {
+            (Some(serial), Some(mfa_token)) => Some(mfa_token(serial)?),
+            (Some(serial), None) => {
+                return Err(error::Error::CredentialsMissing(format!(
+                    "no MFA token available for {}",
+                    serial
+                )))
+            }
+            _ => None,
+        };
+
+        let mut params = vec![
+            ("Action", "AssumeRole"),
+            ("Version", STS_VERSION),
+            ("RoleArn", self.role_arn.as_str()),
+            ("RoleSessionName", role_session_name.as_str()),
+        ];
+        if let Some(external_id) = &self.options.external_id {
+            params.push(("ExternalId", external_id));
+        }
+        if let Some(duration) = &duration {
+            params.push(("DurationSeconds", duration));
+        }
+        if let Some(policy) = &self.options.policy {
+            params.push(("Policy", policy));
+        }
+        if let (Some(serial), Some(token_code)) = (&self.options.mfa_serial, &token_code) {
+            params.push(("SerialNumber", serial));
+            params.push(("TokenCode", token_code));
+        }
+        Ok(form_body(&params))
+    }
+}
+
+impl CredentialsProvider for AssumeRoleProvider {
+    fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+        let source_credentials = self.source.credentials()?;
+        let body = self.build_form()?;
+
+        let mut headers = BTreeMap::new();
+        headers.insert(
+            "content-type".to_string(),
+            vec!["application/x-www-form-urlencoded".to_string()],
+        );
+        let request = PresignerRequest {
+            request_method: "POST".to_string(),
+            url: endpoint_url(&self.endpoint)?,
+            headers,
+            payload: Payload::Bytes(body.clone().into_bytes()),
+        };
+        let params = SigningParams {
+            double_encode_url: true,
+            normalize_path: true,
+            content_sha256_header: false,
+            header_policy: presigner::HeaderPolicy::All,
+            region: self.region.clone(),
+            service_name: "sts".to_string(),
+            expiry: Duration::from_secs(0),
+            timestamp: self.clock.now(),
+        };
+        let auth_headers = presigner::sign_headers(&request, &params, &source_credentials);
+
+        let mut http_request = HttpRequest::new("POST", request.url);
+        for (name, values) in request.headers.iter().chain(auth_headers.iter()) {
+            http_request = http_request.header(name, &values.join(","));
+        }
+        http_request.body = body.into_bytes();
+
+        send(self.client.as_ref(), &http_request)
+    }
+}
+
+/// The STS endpoint to use: `AWS_ENDPOINT_URL_STS` if set, otherwise the regional endpoint for
+/// `AWS_REGION`/`AWS_DEFAULT_REGION`, otherwise the global endpoint.
+pub fn default_endpoint() -> String {
+    if let Some(endpoint) = endpoint_override() {
+        return endpoint;
+    }
+    let lookup = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+    match lookup("AWS_REGION").or_else(|| lookup("AWS_DEFAULT_REGION")) {
+        Some(region) => regional_endpoint(&region),
+        None => "https://sts.amazonaws.com".to_string(),
+    }
+}
+
+fn endpoint_override() -> Option<String> {
+    env::var("AWS_ENDPOINT_URL_STS")
+        .ok()
+        .filter(|v| !v.is_empty())
+}
+
+pub fn regional_endpoint(region: &str) -> String {
+    if region.starts_with("cn-") {
+        format!("https://sts.{}.amazonaws.com.cn", region)
+    } else {
+        format!("https://sts.{}.amazonaws.com", region)
+    }
+}
+
+pub(crate) fn endpoint_url(endpoint: &str) -> Result<Url, error::Error> {
+    Url::parse(endpoint).map_err(|e| error::Error::InvalidEndpoint {
+        endpoint: endpoint.to_string(),
+        message: "invalid STS endpoint".to_string(),
+        source: Some(e),
+    })
+}
+
+pub(crate) fn form_body(params: &[(&str, &str)]) -> String {
+    params
+        .iter()
+        .map(|(k, v)| format!("{}={}", urlencode_param(k), urlencode_param(v)))
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+/// Send a request to STS and parse the `Credentials` element out of the response.
+pub(crate) fn send(
+    client: &dyn HttpClient,
+    request: &HttpRequest,
+) -> Result<SigningCredentials, error::Error> {
+    let response = client.send(request)?;
+    let body = response.body_string();
+    if !response.is_success() {
+        let error = ServiceError::parse(&body);
+        let code = error.as_ref().map(|e| e.code.clone()).unwrap_or_default();
+        let message = error.and_then(|e| e.message).unwrap_or_default();
+        return Err(error::Error::Http {
+            message: format!(
+                "STS request failed with status {}: {} {}",
+                response.status, code, message
+            ),
+            status: Some(response.status),
+        });
+    }
+    credentials_from_response(&body)
+}
+
+pub(crate) fn credentials_from_response(body: &str) -> Result<SigningCredentials, error::Error> {
+    let required = |name: &str| {
+        xml::element_text(body, name)
+            .ok_or_else(|| error::Error::parse(&format!("STS response is missing {}", name)))
+    };
+    Ok(SigningCredentials {
+        access_key_id: required("AccessKeyId")?,
+        secret_access_key: required("SecretAccessKey")?,
+        session_token: Some(required("SessionToken")?),
+        expiration: Some(parse_expiration(&required("Expiration")?)?),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use crate::clock::FixedClock;
+    use crate::credentials::sts::*;
+    use crate::http::{test_server, TcpHttpClient};
+
+    #[test]
+    fn test_credentials_from_response() {
+        let body = r#"<AssumeRoleResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <AssumeRoleResult>
+    <Credentials>
+      <AccessKeyId>ASIAEXAMPLE</AccessKeyId>
+      <SecretAccessKey>secret</SecretAccessKey>
+      <SessionToken>token</SessionToken>
+      <Expiration>2020-01-01T06:00:00Z</Expiration>
+    </Credentials>
+  </AssumeRoleResult>
+</AssumeRoleResponse>"#;
+        let credentials = credentials_from_response(body).unwrap();
+        assert_eq!("ASIAEXAMPLE", credentials.access_key_id);
+        assert_eq!("secret", credentials.secret_access_key);
+        assert_eq!(Some("token".to_string()), credentials.session_token);
+        assert_eq!(
+            Some(Utc.ymd_opt(2020, 1, 1).and_hms_opt(6, 0, 0).unwrap()),
+            credentials.expiration
+        );
+
+        assert!(credentials_from_response("<ErrorResponse/>").is_err());
+    }
+
+    #[test]
+    fn test_form_body() {
+        assert_eq!(
+            "Action=AssumeRole&RoleArn=arn%3Aaws%3Aiam%3A%3A1%3Arole%2Fa%20b",
+            form_body(&[
+                ("Action", "AssumeRole"),
+                ("RoleArn", "arn:aws:iam::1:role/a b")
+            ])
+        );
+    }
+
+    #[test]
+    fn test_assume_role() {
+        let server = test_server::start(|request| {
+            let authorization = request.header("Authorization").unwrap_or("");
+            if authorization.contains("Credential=AKIDSOURCE/")
+                && authorization.contains("/us-west-2/sts/aws4_request")
+            {
+                (
+                    200,
+                    "<AssumeRoleResponse><AssumeRoleResult><Credentials>\
+                     <AccessKeyId>ASIAROLE</AccessKeyId><SecretAccessKey>secret</SecretAccessKey>\
+                     <SessionToken>token</SessionToken><Expiration>2020-01-01T06:00:00Z</Expiration>\
+                     </Credentials></AssumeRoleResult></AssumeRoleResponse>"
+                        .to_string(),
+                )
+            } else {
+                (403, String::new())
+            }
+        });
+
+        let source = SigningCredentials {
+            access_key_id: "AKIDSOURCE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+            expiration: None,
+        };
+        let options = AssumeRoleOptions {
+            role_session_name: Some("session".to_string()),
+            external_id: Some("external".to_string()),
+            duration: Some(Duration::from_secs(900)),
+            mfa_serial: Some("arn:aws:iam::1:mfa/user".to_string()),
+            mfa_token: Some(Arc::new(|_serial| Ok("123456".to_string()))),
+            policy: None,
+        };
+        let provider = AssumeRoleProvider::new(
+            "arn:aws:iam::1:role/r",
+            options,
+            Box::new(source),
+            Box::new(TcpHttpClient::default()),
+        )
+        .with_endpoint(&server.endpoint)
+        // Signs for us-west-2, but the endpoint override is kept
+        .with_region("us-west-2")
+        .with_clock(Box::new(FixedClock(
+            Utc.ymd_opt(2015, 8, 30).and_hms_opt(12, 36, 0).unwrap(),
+        )));
+        let credentials = provider.credentials().unwrap();
+        assert_eq!("ASIAROLE", credentials.access_key_id);
+
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(
+            "Action=AssumeRole&Version=2011-06-15&RoleArn=arn%3Aaws%3Aiam%3A%3A1%3Arole%2Fr\
+             &RoleSessionName=session&ExternalId=external&DurationSeconds=900\
+             &SerialNumber=arn%3Aaws%3Aiam%3A%3A1%3Amfa%2Fuser&TokenCode=123456",
+            requests[0].body
+        );
+        assert_eq!(Some("20150830T123600Z"), requests[0].header("X-Amz-Date"));
+        assert!(requests[0]
+            .header("Authorization")
+            .unwrap()
+            .contains("/20150830/us-west-2/sts/aws4_request"));
+    }
+}
@@ -0,0 +1,231 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::clock::{Clock, SystemClock};
+use crate::credentials::sts;
+use crate::credentials::CredentialsProvider;
+use crate::error;
+use crate::http::{HttpClient, HttpRequest};
+use crate::presigner::SigningCredentials;
+
+/// Exchanges a web identity token (e.g. an EKS service account token) for temporary credentials
+/// using STS `AssumeRoleWithWebIdentity`, which is an unsigned request.
+///
+/// Configured from `AWS_WEB_IDENTITY_TOKEN_FILE`, `AWS_ROLE_ARN` and (optionally)
+/// `AWS_ROLE_SESSION_NAME`. STS is called with the client given to `with_http_client`; if it is
+/// configured but there is no client, `credentials` returns `Error::HttpClientRequired` rather
+/// than letting a credentials chain fall through to another identity.
+pub struct WebIdentityProvider {
+    settings: Option<WebIdentitySettings>,
+    endpoint: String,
+    client: Option<Box<dyn HttpClient>>,
+    clock: Box<dyn Clock>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebIdentitySettings {
+    pub token_file: PathBuf,
+    pub role_arn: String,
+    pub role_session_name: Option<String>,
+}
+
+impl WebIdentityProvider {
+    pub fn new() -> WebIdentityProvider {
+        let lookup = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+        let settings = match (
+            lookup("AWS_WEB_IDENTITY_TOKEN_FILE"),
+            lookup("AWS_ROLE_ARN"),
+        ) {
+            (Some(token_file), Some(role_arn)) => Some(WebIdentitySettings {
+                token_file: PathBuf::from(token_file),
+                role_arn,
+                role_session_name: lookup("AWS_ROLE_SESSION_NAME"),
+            }),
+            _ => None,
+        };
+        WebIdentityProvider {
+            settings,
+            endpoint: sts::default_endpoint(),
+            client: None,
+            clock: Box::new(SystemClock),
+        }
+    }
+
+    pub fn with_settings(settings: WebIdentitySettings) -> WebIdentityProvider {
+        WebIdentityProvider {
+            settings: Some(settings),
+            ..WebIdentityProvider::new()
+        }
+    }
+
+    /// Call STS with `client`, which must support HTTPS.
+    pub fn with_http_client(mut self, client: Box<dyn HttpClient>) -> WebIdentityProvider {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn with_endpoint(mut self, endpoint: &str) -> WebIdentityProvider {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+
+    /// Name sessions (when `role_session_name` isn't set) with the time from `clock`.
+    pub fn with_clock(mut self, clock: Box<dyn Clock>) -> WebIdentityProvider {
+        self.clock = clock;
+        self
+    }
+}
+
+impl Default for WebIdentityProvider {
+    fn default() -> WebIdentityProvider {
+        WebIdentityProvider::new()
+    }
+}
+
+impl CredentialsProvider for WebIdentityProvider {
+    fn credentials(&self) -> Result<SigningCredentials, error::Error> {
+        let settings = self.settings.as_ref().ok_or_else(|| {
+            error::Error::CredentialsMissing(
+                "AWS_WEB_IDENTITY_TOKEN_FILE and AWS_ROLE_ARN are not set".to_string(),
+            )
+        })?;
+        let client = self.client.as_ref().ok_or_else(|| {
+            error::Error::HttpClientRequired(
+                "AWS_WEB_IDENTITY_TOKEN_FILE is set, but exchanging the token with STS needs an \
+                 HttpClient (see WebIdentityProvider::with_http_client)"
+                    .to_string(),
+            )
+        })?;
+
+        let token = read_token(&settings.token_file)?;
+        let role_session_name = settings
+            .role_session_name
+            .clone()
+            .unwrap_or_else(|| format!("aws-presigner-{}", self.clock.now().timestamp_millis()));
+
+        let body = sts::form_body(&[
+            ("Action", "AssumeRoleWithWebIdentity"),
+            ("Version", sts::STS_VERSION),
+            ("RoleArn", &settings.role_arn),
+            ("RoleSessionName", &role_session_name),
+            ("WebIdentityToken", &token),
+        ]);
+        let mut request = HttpRequest::new("POST", sts::endpoint_url(&self.endpoint)?)
+            .header("Content-Type", "application/x-www-form-urlencoded");
+        request.body = body.into_bytes();
+
+        sts::send(client.as_ref(), &request)
+    }
+}
+
+fn read_token(path: &Path) -> Result<String, error::Error> {
+    fs::read_to_string(path)
+        .map(|token| token.trim().to_string())
+        .map_err(|e| error::Error::io(&format!("unable to read {}", path.display()), e))
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::clock::FixedClock;
+    use crate::credentials::web_identity::*;
+    use crate::http::{test_server, TcpHttpClient};
+
+    const RESPONSE: &str = r#"<AssumeRoleWithWebIdentityResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <AssumeRoleWithWebIdentityResult>
+    <SubjectFromWebIdentityToken>system:serviceaccount:default:app</SubjectFromWebIdentityToken>
+    <Credentials>
+      <SessionToken>token</SessionToken>
+      <SecretAccessKey>secret</SecretAccessKey>
+      <Expiration>2020-01-01T06:00:00Z</Expiration>
+      <AccessKeyId>ASIAEXAMPLE</AccessKeyId>
+    </Credentials>
+  </AssumeRoleWithWebIdentityResult>
+</AssumeRoleWithWebIdentityResponse>"#;
+
+    #[test]
+    fn test_credentials() {
+        let token_file = env::temp_dir().join(format!("web-identity-{}", std::process::id()));
+        fs::write(&token_file, "jwt-token\n").unwrap();
+
+        let server = test_server::start(|request| {
+            if request.body.contains("WebIdentityToken=jwt-token") {
+                (200, RESPONSE.to_string())
+            } else {
+                (
+                    400,
+                    "<ErrorResponse><Error><Code>InvalidIdentityToken</Code></Error></ErrorResponse>"
+                        .to_string(),
+                )
+            }
+        });
+
+        let provider = WebIdentityProvider::with_settings(WebIdentitySettings {
+            token_file: token_file.clone(),
+            role_arn: "arn:aws:iam::123456789012:role/app".to_string(),
+            role_session_name: Some("session".to_string()),
+        })
+        .with_http_client(Box::new(TcpHttpClient::default()))
+        .with_endpoint(&server.endpoint);
+        let credentials = provider.credentials().unwrap();
+        fs::remove_file(&token_file).unwrap();
+
+        assert_eq!("ASIAEXAMPLE", credentials.access_key_id);
+        assert_eq!(Some("token".to_string()), credentials.session_token);
+        assert!(credentials.expiration.is_some());
+
+        let requests = server.requests.lock().unwrap();
+        assert_eq!("POST", requests[0].method);
+        assert_eq!(
+            "Action=AssumeRoleWithWebIdentity&Version=2011-06-15\
+             &RoleArn=arn%3Aaws%3Aiam%3A%3A123456789012%3Arole%2Fapp\
+             &RoleSessionName=session&WebIdentityToken=jwt-token",
+            requests[0].body
+        );
+        assert_eq!(None, requests[0].header("Authorization"));
+    }
+
+    #[test]
+    fn test_session_name() {
+        let token_file = env::temp_dir().join(format!("web-identity-name-{}", std::process::id()));
+        fs::write(&token_file, "jwt-token").unwrap();
+
+        let server = test_server::start(|_| (200, RESPONSE.to_string()));
+        let provider = WebIdentityProvider::with_settings(WebIdentitySettings {
+            token_file: token_file.clone(),
+            role_arn: "arn".to_string(),
+            role_session_name: None,
+        })
+        .with_http_client(Box::new(TcpHttpClient::default()))
+        .with_endpoint(&server.endpoint)
+        .with_clock(Box::new(FixedClock(
+            Utc.ymd_opt(2015, 8, 30).and_hms_opt(12, 36, 0).unwrap(),
+        )));
+        provider.credentials().unwrap();
+        fs::remove_file(&token_file).unwrap();
+
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[0]
+            .body
+            .contains("&RoleSessionName=aws-presigner-1440938160000&"));
+    }
+
+    #[test]
+    fn test_missing_token_file() {
+        let provider = WebIdentityProvider::with_settings(WebIdentitySettings {
+            token_file: PathBuf::from("/nonexistent/token"),
+            role_arn: "arn".to_string(),
+            role_session_name: None,
+        })
+        .with_http_client(Box::new(TcpHttpClient::default()));
+        assert!(matches!(
+            provider.credentials(),
+            Err(error::Error::Io { .. })
+        ));
+    }
+}
@@ -1,23 +1,181 @@
 use core::fmt;
 use std::error;
+use std::io;
+use std::time::Duration;
 
-#[derive(Debug, Clone)]
-pub struct Error {
-    pub message: String,
+use chrono::{DateTime, Utc};
+
+#[derive(Debug)]
+pub enum Error {
+    /// A URL or endpoint (RDS host/port, STS, instance metadata, ...) could not be used.
+    InvalidEndpoint {
+        endpoint: String,
+        message: String,
+        source: Option<url::ParseError>,
+    },
+    InvalidRegion(String),
+    /// An S3 bucket and key cannot be addressed as requested.
+    InvalidObject {
+        bucket: String,
+        key: String,
+        message: String,
+    },
+    /// No provider was configured to supply credentials.
+    CredentialsMissing(String),
+    /// Credentials were configured but only partially (e.g. an access key without a secret).
+    CredentialsIncomplete(String),
+    CredentialsExpired {
+        expiration: DateTime<Utc>,
+    },
+    ExpiryOutOfRange {
+        expiry: Duration,
+        max: Duration,
+    },
+    /// The chunks of an `aws-chunked` upload (other than the last) must be at least `min` bytes.
+    ChunkSizeOutOfRange {
+        chunk_size: usize,
+        min: usize,
+    },
+    /// Credentials are configured with a source that is fetched over HTTPS (e.g. a web identity
+    /// token exchanged with STS), but no `HttpClient` was given to reach it.
+    HttpClientRequired(String),
+    /// A profile in the shared config files is set up incorrectly.
+    InvalidProfile {
+        profile: String,
+        message: String,
+    },
+    /// Reading a file or talking to a credentials endpoint failed.
+    Io {
+        message: String,
+        source: io::Error,
+    },
+    /// A credentials endpoint returned an unexpected response.
+    Http {
+        message: String,
+        status: Option<u16>,
+    },
+    /// A config file or response document could not be parsed.
+    Parse {
+        message: String,
+        source: Option<Box<dyn error::Error + Send + Sync>>,
+    },
+    /// A `credential_process` command exited unsuccessfully.
+    ProcessFailed {
+        command: String,
+        exit_code: Option<i32>,
+        stderr: String,
+    },
+    /// A `credential_process` command succeeded but its output could not be used.
+    ProcessOutput {
+        command: String,
+        source: Box<Error>,
+    },
 }
 
 impl Error {
-    pub fn new(message: &str) -> Error {
-        Error {
+    pub fn parse(message: &str) -> Error {
+        Error::Parse {
+            message: message.to_string(),
+            source: None,
+        }
+    }
+
+    pub fn io(message: &str, source: io::Error) -> Error {
+        Error::Io {
             message: message.to_string(),
+            source,
+        }
+    }
+
+    /// Whether retrying might succeed: I/O failures, throttling and server-side errors, as opposed
+    /// to misconfiguration or missing credentials.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Io { .. } => true,
+            Error::Http { status, .. } => match status {
+                Some(status) => *status == 429 || *status >= 500,
+                None => true,
+            },
+            _ => false,
         }
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.message)
+        match self {
+            Error::InvalidEndpoint {
+                endpoint, message, ..
+            } => write!(f, "invalid endpoint {}: {}", endpoint, message),
+            Error::InvalidRegion(region) => write!(f, "invalid region {:?}", region),
+            Error::InvalidObject {
+                bucket,
+                key,
+                message,
+            } => write!(f, "invalid object s3://{}/{}: {}", bucket, key, message),
+            Error::CredentialsMissing(message) => write!(f, "credentials missing: {}", message),
+            Error::CredentialsIncomplete(message) => {
+                write!(f, "credentials incomplete: {}", message)
+            }
+            Error::CredentialsExpired { expiration } => {
+                write!(f, "credentials expired at {}", expiration.to_rfc3339())
+            }
+            Error::ExpiryOutOfRange { expiry, max } => write!(
+                f,
+                "expiry of {} seconds is out of range (maximum {} seconds)",
+                expiry.as_secs(),
+                max.as_secs()
+            ),
+            Error::ChunkSizeOutOfRange { chunk_size, min } => write!(
+                f,
+                "chunk size of {} bytes is out of range (minimum {} bytes)",
+                chunk_size, min
+            ),
+            Error::HttpClientRequired(message) => write!(f, "HTTP client required: {}", message),
+            Error::InvalidProfile { profile, message } => {
+                write!(f, "invalid profile {}: {}", profile, message)
+            }
+            Error::Io { message, source } => write!(f, "{}: {}", message, source),
+            Error::Http { message, .. } => write!(f, "{}", message),
+            Error::Parse { message, source } => match source {
+                Some(source) => write!(f, "{}: {}", message, source),
+                None => write!(f, "{}", message),
+            },
+            Error::ProcessFailed {
+                command,
+                exit_code,
+                stderr,
+            } => {
+                match exit_code {
+                    Some(code) => write!(f, "{} failed with exit code {}", command, code)?,
+                    None => write!(f, "{} was terminated by a signal", command)?,
+                }
+                if !stderr.is_empty() {
+                    write!(f, ": {}", stderr)?;
+                }
+                Ok(())
+            }
+            Error::ProcessOutput { command, source } => {
+                write!(f, "{} returned invalid output: {}", command, source)
+            }
+        }
     }
 }
 
-impl error::Error for Error {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::InvalidEndpoint {
+                source: Some(source),
+                ..
+            } => Some(source),
+            Error::Io { source, .. } => Some(source),
+            Error::Parse {
+                source: Some(source),
+                ..
+            } => Some(source.as_ref()),
+            Error::ProcessOutput { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
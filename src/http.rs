@@ -0,0 +1,404 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use url::Url;
+
+use crate::error;
+
+pub struct HttpRequest {
+    pub method: String,
+    pub url: Url,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn new(method: &str, url: Url) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            url,
+            headers: vec![],
+            body: vec![],
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> HttpRequest {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    pub fn body_string(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+}
+
+/// The transport used by credential providers that need to make HTTP calls.
+///
+/// The built-in `TcpHttpClient` only speaks plain HTTP (which is all that the instance metadata
+/// and container credential endpoints need), so providers that talk to HTTPS endpoints such as STS
+/// accept an alternative implementation backed by a TLS-capable client.
+pub trait HttpClient: Send + Sync {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, error::Error>;
+}
+
+impl<T: HttpClient + ?Sized> HttpClient for Arc<T> {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, error::Error> {
+        (**self).send(request)
+    }
+}
+
+/// Responses larger than this are rejected. Credential documents and error responses are a few
+/// kilobytes at most.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// A minimal HTTP/1.1 client over `std::net::TcpStream`. Only `http://` URLs are supported.
+#[derive(Debug, Clone)]
+pub(crate) struct TcpHttpClient {
+    pub(crate) connect_timeout: Duration,
+    pub(crate) timeout: Duration,
+}
+
+impl TcpHttpClient {
+    pub(crate) fn new(timeout: Duration) -> TcpHttpClient {
+        TcpHttpClient {
+            connect_timeout: timeout,
+            timeout,
+        }
+    }
+
+    pub(crate) fn with_connect_timeout(mut self, connect_timeout: Duration) -> TcpHttpClient {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+}
+
+impl Default for TcpHttpClient {
+    fn default() -> TcpHttpClient {
+        TcpHttpClient::new(Duration::from_secs(5))
+    }
+}
+
+impl HttpClient for TcpHttpClient {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, error::Error> {
+        if request.url.scheme() != "http" {
+            return Err(error::Error::InvalidEndpoint {
+                endpoint: request.url.to_string(),
+                message: format!(
+                    "unsupported URL scheme {} (only http is supported)",
+                    request.url.scheme()
+                ),
+                source: None,
+            });
+        }
+        let host = request
+            .url
+            .host_str()
+            .ok_or_else(|| error::Error::InvalidEndpoint {
+                endpoint: request.url.to_string(),
+                message: "URL has no host".to_string(),
+                source: None,
+            })?;
+        let port = request.url.port_or_known_default().unwrap_or(80);
+
+        let address = (host, port)
+            .to_socket_addrs()
+            .map_err(io_error)?
+            .next()
+            .ok_or_else(|| {
+                error::Error::io(
+                    "HTTP request failed",
+                    std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("unable to resolve {}", host),
+                    ),
+                )
+            })?;
+        let mut stream =
+            TcpStream::connect_timeout(&address, self.connect_timeout).map_err(io_error)?;
+        stream
+            .set_read_timeout(Some(self.timeout))
+            .map_err(io_error)?;
+        stream
+            .set_write_timeout(Some(self.timeout))
+            .map_err(io_error)?;
+
+        let mut path = request.url.path().to_string();
+        if let Some(query) = request.url.query() {
+            path.push('?');
+            path.push_str(query);
+        }
+        let mut head = format!("{} {} HTTP/1.1\r\n", request.method, path);
+        match request.url.port() {
+            Some(port) => head.push_str(&format!("Host: {}:{}\r\n", host, port)),
+            None => head.push_str(&format!("Host: {}\r\n", host)),
+        }
+        for (name, value) in &request.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!("Content-Length: {}\r\n", request.body.len()));
+        head.push_str("Connection: close\r\n\r\n");
+
+        stream.write_all(head.as_bytes()).map_err(io_error)?;
+        stream.write_all(&request.body).map_err(io_error)?;
+        stream.flush().map_err(io_error)?;
+
+        read_response(BufReader::new(stream))
+    }
+}
+
+fn read_response<R: BufRead>(mut reader: R) -> Result<HttpResponse, error::Error> {
+    let status_line = read_line(&mut reader)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or_else(|| error::Error::Http {
+            message: format!("invalid HTTP status line: {}", status_line),
+            status: None,
+        })?;
+
+    let mut headers = vec![];
+    loop {
+        let line = read_line(&mut reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(colon) = line.find(':') {
+            headers.push((
+                line[..colon].trim().to_string(),
+                line[colon + 1..].trim().to_string(),
+            ));
+        }
+    }
+
+    let mut response = HttpResponse {
+        status,
+        headers,
+        body: vec![],
+    };
+
+    let chunked = response
+        .header("Transfer-Encoding")
+        .map(|v| v.eq_ignore_ascii_case("chunked"))
+        .unwrap_or(false);
+    let content_length = response
+        .header("Content-Length")
+        .and_then(|v| v.parse::<u64>().ok());
+
+    if chunked {
+        loop {
+            let size_line = read_line(&mut reader)?;
+            let size_hex = size_line.split(';').next().unwrap_or("").trim();
+            let size = usize::from_str_radix(size_hex, 16).map_err(|_| error::Error::Http {
+                message: format!("invalid chunk size: {}", size_line),
+                status: Some(status),
+            })?;
+            if size == 0 {
+                break;
+            }
+            if size > MAX_BODY_SIZE - response.body.len() {
+                return Err(too_large(status));
+            }
+            let start = response.body.len();
+            response.body.resize(start + size, 0);
+            reader
+                .read_exact(&mut response.body[start..])
+                .map_err(io_error)?;
+            read_line(&mut reader)?;
+        }
+    } else {
+        if content_length.is_some_and(|length| length > MAX_BODY_SIZE as u64) {
+            return Err(too_large(status));
+        }
+        let limit = content_length.unwrap_or(MAX_BODY_SIZE as u64 + 1);
+        reader
+            .take(limit)
+            .read_to_end(&mut response.body)
+            .map_err(io_error)?;
+        if response.body.len() > MAX_BODY_SIZE {
+            return Err(too_large(status));
+        }
+    }
+
+    Ok(response)
+}
+
+fn too_large(status: u16) -> error::Error {
+    error::Error::Http {
+        message: format!("response body is larger than {} bytes", MAX_BODY_SIZE),
+        status: Some(status),
+    }
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, error::Error> {
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(io_error)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn io_error(e: std::io::Error) -> error::Error {
+    error::Error::io("HTTP request failed", e)
+}
+
+/// A tiny HTTP server for exercising the credential providers in tests. Each incoming request is
+/// passed to the handler, which returns the status and body to respond with.
+#[cfg(test)]
+pub(crate) mod test_server {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    #[derive(Debug, Clone)]
+    pub struct ReceivedRequest {
+        pub method: String,
+        pub path: String,
+        pub headers: Vec<(String, String)>,
+        pub body: String,
+    }
+
+    impl ReceivedRequest {
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+    }
+
+    pub struct TestServer {
+        pub endpoint: String,
+        pub requests: Arc<Mutex<Vec<ReceivedRequest>>>,
+    }
+
+    pub fn start<F>(handler: F) -> TestServer
+    where
+        F: Fn(&ReceivedRequest) -> (u16, String) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => break,
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut parts = request_line.split_whitespace();
+                let method = parts.next().unwrap_or("").to_string();
+                let path = parts.next().unwrap_or("").to_string();
+
+                let mut headers = vec![];
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let colon = line.find(':').unwrap();
+                    headers.push((
+                        line[..colon].trim().to_string(),
+                        line[colon + 1..].trim().to_string(),
+                    ));
+                }
+                let content_length = headers
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case("Content-Length"))
+                    .map(|(_, v)| v.parse::<usize>().unwrap())
+                    .unwrap_or(0);
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let request = ReceivedRequest {
+                    method,
+                    path,
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                };
+                let (status, body) = handler(&request);
+                received.lock().unwrap().push(request);
+
+                let mut stream = stream;
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+            }
+        });
+
+        TestServer { endpoint, requests }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::error::Error;
+    use crate::http::{read_response, MAX_BODY_SIZE};
+
+    #[test]
+    fn test_chunked_response() {
+        let raw = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6; ext=1\r\n world\r\n0\r\n\r\n";
+        let response = read_response(Cursor::new(raw)).unwrap();
+        assert_eq!(200, response.status);
+        assert_eq!("hello world", response.body_string());
+    }
+
+    #[test]
+    fn test_content_length_response() {
+        let raw = "HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\n\r\nnopeextra";
+        let response = read_response(Cursor::new(raw)).unwrap();
+        assert_eq!(404, response.status);
+        assert!(!response.is_success());
+        assert_eq!("nope", response.body_string());
+    }
+
+    #[test]
+    fn test_body_limit() {
+        let raw = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffff\r\n";
+        assert!(matches!(
+            read_response(Cursor::new(raw)),
+            Err(Error::Http { .. })
+        ));
+
+        let raw = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        assert!(read_response(Cursor::new(raw)).is_err());
+
+        let raw = format!("HTTP/1.1 200 OK\r\n\r\n{}", "x".repeat(MAX_BODY_SIZE + 1));
+        assert!(read_response(Cursor::new(raw)).is_err());
+    }
+}
@@ -1,4 +1,13 @@
+pub mod clock;
+pub mod credentials;
 pub mod error;
+pub mod http;
+mod json;
 pub mod presigner;
 pub mod rds;
+pub mod s3;
+pub mod service_error;
+pub mod signing_key;
 pub mod util;
+pub mod verifier;
+mod xml;
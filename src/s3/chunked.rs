@@ -0,0 +1,625 @@
+//! Uploads with `aws-chunked` encoding, where the body is sent as a series of chunks that are each
+//! signed. The first chunk's signature chains from the signature of the request headers (the seed
+//! signature), and each later chunk's from the one before it.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use sha2::{Digest, Sha256};
+
+use crate::error;
+use crate::presigner::{
+    build_credential_scope, derive_signing_key, sign_headers_detailed, Payload, PresignerRequest,
+    SigningCredentials, SigningDetails, SigningParams,
+};
+use crate::util::*;
+
+/// S3 requires every chunk except the last to be at least 8 KiB.
+pub const MIN_CHUNK_SIZE: usize = 8 * 1024;
+
+const SIGNATURE_LENGTH: usize = 64;
+
+/// The checksum sent in the trailer of an upload.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChecksumAlgorithm {
+    Crc32,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    pub fn header_name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Crc32 => "x-amz-checksum-crc32",
+            ChecksumAlgorithm::Sha256 => "x-amz-checksum-sha256",
+        }
+    }
+
+    /// The length of the base64-encoded checksum.
+    fn encoded_length(&self) -> usize {
+        match self {
+            ChecksumAlgorithm::Crc32 => 8,
+            ChecksumAlgorithm::Sha256 => 44,
+        }
+    }
+}
+
+enum Checksum {
+    Crc32(u32),
+    Sha256(Sha256),
+}
+
+impl Checksum {
+    fn new(algorithm: ChecksumAlgorithm) -> Checksum {
+        match algorithm {
+            ChecksumAlgorithm::Crc32 => Checksum::Crc32(0),
+            ChecksumAlgorithm::Sha256 => Checksum::Sha256(Sha256::new()),
+        }
+    }
+
+    fn header_name(&self) -> &'static str {
+        match self {
+            Checksum::Crc32(_) => ChecksumAlgorithm::Crc32.header_name(),
+            Checksum::Sha256(_) => ChecksumAlgorithm::Sha256.header_name(),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Checksum::Crc32(crc) => *crc = crc32(*crc, data),
+            Checksum::Sha256(hasher) => hasher.input(data),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Checksum::Crc32(crc) => base64_encode(&crc.to_be_bytes()),
+            Checksum::Sha256(hasher) => base64_encode(&hasher.result()),
+        }
+    }
+}
+
+/// CRC-32 (as used by zlib and Ethernet), continuing from `crc`.
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Signs the chunks of a body in order, starting from the seed signature.
+pub struct ChunkSigner {
+    signing_key: Vec<u8>,
+    timestamp: String,
+    credential_scope: String,
+    previous_signature: String,
+}
+
+impl ChunkSigner {
+    pub fn new(
+        params: &SigningParams,
+        credentials: &SigningCredentials,
+        seed_signature: &str,
+    ) -> ChunkSigner {
+        ChunkSigner {
+            signing_key: derive_signing_key(
+                &credentials.secret_access_key,
+                &params.timestamp,
+                &params.region,
+                &params.service_name,
+            ),
+            timestamp: to_timestamp_string(&params.timestamp),
+            credential_scope: build_credential_scope(
+                &params.timestamp,
+                &params.region,
+                &params.service_name,
+            ),
+            previous_signature: seed_signature.to_string(),
+        }
+    }
+
+    /// Sign the next chunk. The body ends with an empty chunk.
+    pub fn sign_chunk(&mut self, data: &[u8]) -> String {
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256-PAYLOAD\n{}\n{}\n{}\n{}\n{}",
+            self.timestamp,
+            self.credential_scope,
+            self.previous_signature,
+            hex_encode(&hash(&[])),
+            hex_encode(&hash(data))
+        );
+        self.chain(&string_to_sign)
+    }
+
+    /// Sign the trailing headers, which follow the final (empty) chunk.
+    pub fn sign_trailer(&mut self, trailer: &[(String, String)]) -> String {
+        let trailer: String = trailer
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256-TRAILER\n{}\n{}\n{}\n{}",
+            self.timestamp,
+            self.credential_scope,
+            self.previous_signature,
+            hex_encode(&hash(trailer.as_bytes()))
+        );
+        self.chain(&string_to_sign)
+    }
+
+    fn chain(&mut self, string_to_sign: &str) -> String {
+        let signature = sign(&self.signing_key, string_to_sign);
+        self.previous_signature = signature.clone();
+        signature
+    }
+}
+
+/// A request whose headers have been signed, ready for its body to be sent with `aws-chunked`
+/// encoding through `reader` or `writer`.
+pub struct ChunkedUpload {
+    /// The headers to add to the request, including `Authorization` and `Content-Length`.
+    pub headers: BTreeMap<String, Vec<String>>,
+    /// The details of the seed signature.
+    pub details: SigningDetails,
+    encoder: Encoder,
+}
+
+impl ChunkedUpload {
+    /// Encode the body as it is read from `source`.
+    pub fn reader<R: Read>(self, source: R) -> ChunkedReader<R> {
+        ChunkedReader {
+            source,
+            encoder: self.encoder,
+            buffer: vec![],
+            position: 0,
+            finished: false,
+        }
+    }
+
+    /// Encode the body as it is written, into `sink`. `ChunkedWriter::finish` must be called after
+    /// the last write.
+    pub fn writer<W: Write>(self, sink: W) -> ChunkedWriter<W> {
+        ChunkedWriter {
+            sink,
+            encoder: self.encoder,
+            buffer: vec![],
+        }
+    }
+}
+
+/// Sign the headers of a request whose body of `decoded_length` bytes will be sent in chunks of
+/// `chunk_size` bytes (the last may be smaller, but the others must be at least
+/// `MIN_CHUNK_SIZE`), optionally followed by a checksum trailer.
+pub fn sign_chunked_upload(
+    request: &PresignerRequest,
+    params: &SigningParams,
+    credentials: &SigningCredentials,
+    decoded_length: u64,
+    chunk_size: usize,
+    checksum: Option<ChecksumAlgorithm>,
+) -> Result<ChunkedUpload, error::Error> {
+    if chunk_size < MIN_CHUNK_SIZE {
+        return Err(error::Error::ChunkSizeOutOfRange {
+            chunk_size,
+            min: MIN_CHUNK_SIZE,
+        });
+    }
+
+    let mut headers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    headers.insert(
+        "Content-Encoding".to_string(),
+        vec!["aws-chunked".to_string()],
+    );
+    headers.insert(
+        "Content-Length".to_string(),
+        vec![encoded_length(decoded_length, chunk_size, checksum).to_string()],
+    );
+    headers.insert(
+        "x-amz-decoded-content-length".to_string(),
+        vec![decoded_length.to_string()],
+    );
+    if let Some(algorithm) = checksum {
+        headers.insert(
+            "x-amz-trailer".to_string(),
+            vec![algorithm.header_name().to_string()],
+        );
+    }
+
+    let mut request_headers: BTreeMap<String, Vec<String>> = request
+        .headers
+        .iter()
+        .filter(|(k, _)| !headers.keys().any(|h| h.eq_ignore_ascii_case(k)))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    request_headers.extend(headers.clone());
+    let chunked_request = PresignerRequest {
+        request_method: request.request_method.clone(),
+        url: request.url.clone(),
+        headers: request_headers,
+        payload: match checksum {
+            Some(_) => Payload::StreamingTrailer,
+            None => Payload::Streaming,
+        },
+    };
+    let params = SigningParams {
+        content_sha256_header: true,
+        ..params.clone()
+    };
+
+    let output = sign_headers_detailed(&chunked_request, &params, credentials);
+    headers.extend(output.headers);
+
+    Ok(ChunkedUpload {
+        headers,
+        encoder: Encoder {
+            signer: ChunkSigner::new(&params, credentials, &output.details.signature),
+            checksum: checksum.map(Checksum::new),
+            chunk_size,
+            decoded_length,
+            length: 0,
+        },
+        details: output.details,
+    })
+}
+
+/// The length of the encoded body, for the `Content-Length` header.
+pub fn encoded_length(
+    decoded_length: u64,
+    chunk_size: usize,
+    checksum: Option<ChecksumAlgorithm>,
+) -> u64 {
+    let chunk_length = |size: u64| {
+        let header = format!("{:x};chunk-signature=", size).len() + SIGNATURE_LENGTH + 2;
+        header as u64 + size + 2
+    };
+    let chunk_size = chunk_size as u64;
+
+    let mut length = decoded_length / chunk_size * chunk_length(chunk_size);
+    let last_chunk_size = decoded_length % chunk_size;
+    if last_chunk_size > 0 {
+        length += chunk_length(last_chunk_size);
+    }
+    match checksum {
+        None => length + chunk_length(0),
+        Some(algorithm) => {
+            let trailer = algorithm.header_name().len() + 1 + algorithm.encoded_length() + 2;
+            let signature = "x-amz-trailer-signature:".len() + SIGNATURE_LENGTH + 2;
+            length + chunk_length(0) - 2 + (trailer + signature + 2) as u64
+        }
+    }
+}
+
+struct Encoder {
+    signer: ChunkSigner,
+    checksum: Option<Checksum>,
+    chunk_size: usize,
+    decoded_length: u64,
+    length: u64,
+}
+
+impl Encoder {
+    fn chunk(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        self.length += data.len() as u64;
+        if self.length > self.decoded_length {
+            return Err(self.length_mismatch());
+        }
+        if let Some(checksum) = &mut self.checksum {
+            checksum.update(data);
+        }
+
+        let signature = self.signer.sign_chunk(data);
+        let mut framed = format!("{:x};chunk-signature={}\r\n", data.len(), signature).into_bytes();
+        framed.extend_from_slice(data);
+        framed.extend_from_slice(b"\r\n");
+        Ok(framed)
+    }
+
+    fn finish(&mut self) -> io::Result<Vec<u8>> {
+        if self.length != self.decoded_length {
+            return Err(self.length_mismatch());
+        }
+
+        let signature = self.signer.sign_chunk(&[]);
+        let mut framed = format!("0;chunk-signature={}\r\n", signature);
+        match self.checksum.take() {
+            None => framed.push_str("\r\n"),
+            Some(checksum) => {
+                let trailer = vec![(checksum.header_name().to_string(), checksum.finish())];
+                let trailer_signature = self.signer.sign_trailer(&trailer);
+                for (name, value) in trailer {
+                    framed.push_str(&format!("{}:{}\r\n", name, value));
+                }
+                framed.push_str(&format!(
+                    "x-amz-trailer-signature:{}\r\n\r\n",
+                    trailer_signature
+                ));
+            }
+        }
+        Ok(framed.into_bytes())
+    }
+
+    fn length_mismatch(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "body does not match its declared length of {} bytes",
+                self.decoded_length
+            ),
+        )
+    }
+}
+
+/// Reads the encoded body of a `ChunkedUpload` from the unencoded body.
+pub struct ChunkedReader<R: Read> {
+    source: R,
+    encoder: Encoder,
+    buffer: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: Read> ChunkedReader<R> {
+    fn fill(&mut self) -> io::Result<()> {
+        let mut chunk = vec![0; self.encoder.chunk_size];
+        let mut length = 0;
+        while length < chunk.len() {
+            match self.source.read(&mut chunk[length..]) {
+                Ok(0) => break,
+                Ok(n) => length += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.buffer = if length > 0 {
+            self.encoder.chunk(&chunk[..length])?
+        } else {
+            self.finished = true;
+            self.encoder.finish()?
+        };
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.buffer.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+
+        let n = cmp::min(buf.len(), self.buffer.len() - self.position);
+        buf[..n].copy_from_slice(&self.buffer[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// Writes the encoded body of a `ChunkedUpload` to a sink as the unencoded body is written.
+pub struct ChunkedWriter<W: Write> {
+    sink: W,
+    encoder: Encoder,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    /// Write the remaining data, the final chunk and the trailer, returning the sink.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.buffer.is_empty() {
+            let framed = self.encoder.chunk(&self.buffer)?;
+            self.sink.write_all(&framed)?;
+        }
+        let framed = self.encoder.finish()?;
+        self.sink.write_all(&framed)?;
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = cmp::min(data.len(), self.encoder.chunk_size - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..n]);
+        if self.buffer.len() == self.encoder.chunk_size {
+            let framed = self.encoder.chunk(&self.buffer)?;
+            self.sink.write_all(&framed)?;
+            self.buffer.clear();
+        }
+        Ok(n)
+    }
+
+    /// Flushes the sink. Data is only written in whole chunks, so some may remain buffered until
+    /// `finish`.
+    fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::io::{Read, Write};
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use url::Url;
+
+    use crate::error;
+    use crate::presigner::{HeaderPolicy, Payload, PresignerRequest};
+    use crate::s3::chunked::*;
+    use crate::s3::test_util::credentials;
+
+    fn request() -> PresignerRequest {
+        let mut headers = BTreeMap::new();
+        headers.insert("Host".to_string(), vec!["s3.amazonaws.com".to_string()]);
+        headers.insert(
+            "x-amz-storage-class".to_string(),
+            vec!["REDUCED_REDUNDANCY".to_string()],
+        );
+        PresignerRequest {
+            request_method: "PUT".to_string(),
+            url: Url::parse("https://s3.amazonaws.com/examplebucket/chunkObject.txt").unwrap(),
+            headers,
+            payload: Payload::Streaming,
+        }
+    }
+
+    fn params() -> SigningParams {
+        SigningParams {
+            double_encode_url: false,
+            normalize_path: false,
+            content_sha256_header: true,
+            header_policy: HeaderPolicy::All,
+            region: "us-east-1".to_string(),
+            service_name: "s3".to_string(),
+            expiry: Duration::from_secs(0),
+            timestamp: Utc.ymd_opt(2013, 5, 24).and_hms_opt(0, 0, 0).unwrap(),
+        }
+    }
+
+    fn upload(checksum: Option<ChecksumAlgorithm>) -> ChunkedUpload {
+        sign_chunked_upload(
+            &request(),
+            &params(),
+            &credentials(),
+            66560,
+            64 * 1024,
+            checksum,
+        )
+        .unwrap()
+    }
+
+    fn encode(upload: ChunkedUpload) -> Vec<u8> {
+        let mut encoded = vec![];
+        upload
+            .reader(&[b'a'; 66560][..])
+            .read_to_end(&mut encoded)
+            .unwrap();
+        encoded
+    }
+
+    #[test]
+    fn test_chunk_size() {
+        let result = sign_chunked_upload(
+            &request(),
+            &params(),
+            &credentials(),
+            66560,
+            MIN_CHUNK_SIZE - 1,
+            None,
+        );
+        assert!(matches!(
+            result,
+            Err(error::Error::ChunkSizeOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(0, crc32(0, b""));
+        assert_eq!(0xcbf4_3926, crc32(0, b"123456789"));
+        assert_eq!(0xcbf4_3926, crc32(crc32(0, b"1234"), b"56789"));
+    }
+
+    // From https://docs.aws.amazon.com/AmazonS3/latest/API/sigv4-streaming.html
+    #[test]
+    fn test_streaming() {
+        let upload = upload(None);
+        assert_eq!(
+            "4f232c4386841ef735655705268965c44a0e4690baa4adea153f7db9fa80a0a9",
+            upload.details.signature
+        );
+        assert_eq!(vec!["66824"], upload.headers["Content-Length"]);
+        assert_eq!(vec!["aws-chunked"], upload.headers["Content-Encoding"]);
+        assert_eq!(
+            vec!["STREAMING-AWS4-HMAC-SHA256-PAYLOAD"],
+            upload.headers["X-Amz-Content-Sha256"]
+        );
+
+        let encoded = encode(upload);
+        assert_eq!(66824, encoded.len());
+        let text = String::from_utf8(encoded).unwrap();
+        assert!(text.starts_with(
+            "10000;chunk-signature=\
+             ad80c730a21e5b8d04586a2213dd63b9a0e99e0e2307b0ade35a65485a288648\r\naaa"
+        ));
+        assert!(text.contains(
+            "a\r\n400;chunk-signature=\
+             0055627c9e194cb4542bae2aa5492e3c1575bbb81b612b7d234b86a503ef5497\r\naaa"
+        ));
+        assert!(text.ends_with(
+            "a\r\n0;chunk-signature=\
+             b6c6ea8a5354eaf15b3cb7646744f4275b71ea724fed81ceb9323e279d449df9\r\n\r\n"
+        ));
+    }
+
+    #[test]
+    fn test_trailer() {
+        let upload = upload(Some(ChecksumAlgorithm::Crc32));
+        assert_eq!(
+            vec!["STREAMING-AWS4-HMAC-SHA256-PAYLOAD-TRAILER"],
+            upload.headers["X-Amz-Content-Sha256"]
+        );
+        assert_eq!(
+            vec!["x-amz-checksum-crc32"],
+            upload.headers["x-amz-trailer"]
+        );
+        let content_length: usize = upload.headers["Content-Length"][0].parse().unwrap();
+
+        let encoded = encode(upload);
+        assert_eq!(content_length, encoded.len());
+        let text = String::from_utf8(encoded).unwrap();
+        assert_eq!(66945, content_length);
+        assert!(text.ends_with(
+            "a\r\n0;chunk-signature=\
+             c519648dc6fb36cc1df946e1d5f8fbef2291de9a2a5982bf3de31d17633a68d8\r\n\
+             x-amz-checksum-crc32:sK4Y7A==\r\n\
+             x-amz-trailer-signature:\
+             9f55152432d960861682c229030f618e232915b0f12574b9158172bce1702fb6\r\n\r\n"
+        ));
+
+        let sha256 = encode(self::upload(Some(ChecksumAlgorithm::Sha256)));
+        assert_eq!(
+            encoded_length(66560, 64 * 1024, Some(ChecksumAlgorithm::Sha256)),
+            sha256.len() as u64
+        );
+    }
+
+    #[test]
+    fn test_writer() {
+        for checksum in [None, Some(ChecksumAlgorithm::Sha256)] {
+            let mut writer = upload(checksum).writer(vec![]);
+            for _ in 0..65 {
+                writer.write_all(&[b'a'; 1024]).unwrap();
+            }
+            assert_eq!(encode(upload(checksum)), writer.finish().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_length_mismatch() {
+        let mut encoded = vec![];
+        assert!(upload(None)
+            .reader(&[b'a'; 100][..])
+            .read_to_end(&mut encoded)
+            .is_err());
+
+        let mut writer = upload(None).writer(vec![]);
+        writer.write_all(&[b'a'; 66561]).unwrap();
+        assert!(writer.finish().is_err());
+        let mut writer = upload(None).writer(vec![]);
+        writer.write_all(&[b'a'; 100]).unwrap();
+        assert!(writer.finish().is_err());
+    }
+}
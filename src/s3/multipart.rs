@@ -0,0 +1,254 @@
+use std::time::Duration;
+
+use crate::clock::{Clock, SystemClock};
+use crate::error;
+use crate::presigner::{self, SigningCredentials, SigningParams};
+use crate::s3::{check_presign, object_request, signing_params, ObjectRequest, PresignedObject};
+use crate::xml;
+
+/// S3 allows at most 10,000 parts, numbered from 1.
+pub const MAX_PARTS: u32 = 10_000;
+
+/// Presigns the requests of a multipart upload, so that a client can upload an object in parts
+/// directly. All of the URLs share a signing time (and so expire together) and one derived
+/// signing key, which makes presigning a large batch of parts cheap.
+pub struct MultipartPresigner {
+    object: ObjectRequest,
+    credentials: SigningCredentials,
+    params: SigningParams,
+    signing_key: Vec<u8>,
+}
+
+impl MultipartPresigner {
+    /// The headers and SSE-C key of `object` apply to `create` (and the SSE-C key to each part);
+    /// its version id and response overrides are not used.
+    pub fn new(
+        object: &ObjectRequest,
+        credentials: &SigningCredentials,
+        expiry: &Duration,
+    ) -> Result<MultipartPresigner, error::Error> {
+        MultipartPresigner::new_with_clock(&SystemClock, object, credentials, expiry)
+    }
+
+    /// `new`, with the signing time taken from `clock`.
+    pub fn new_with_clock(
+        clock: &dyn Clock,
+        object: &ObjectRequest,
+        credentials: &SigningCredentials,
+        expiry: &Duration,
+    ) -> Result<MultipartPresigner, error::Error> {
+        let timestamp = clock.now();
+        check_presign(&object.region, expiry, credentials, &timestamp)?;
+        // Check that the object can be addressed before any URLs are requested
+        object_request("POST", object, &[])?;
+
+        let params = signing_params(&object.region, expiry, timestamp);
+        let signing_key = presigner::derive_signing_key(
+            &credentials.secret_access_key,
+            &params.timestamp,
+            &params.region,
+            &params.service_name,
+        );
+        Ok(MultipartPresigner {
+            object: object.clone(),
+            credentials: credentials.clone(),
+            params,
+            signing_key,
+        })
+    }
+
+    /// `CreateMultipartUpload`. Its response contains the id of the upload (see `upload_id`).
+    pub fn create(&self) -> Result<PresignedObject, error::Error> {
+        self.presign("POST", &self.object, &[("uploads", "")])
+    }
+
+    /// `UploadPart`, for a part number from 1 to `MAX_PARTS`.
+    pub fn upload_part(
+        &self,
+        upload_id: &str,
+        part_number: u32,
+    ) -> Result<PresignedObject, error::Error> {
+        if part_number == 0 || part_number > MAX_PARTS {
+            return Err(error::Error::InvalidObject {
+                bucket: self.object.bucket.clone(),
+                key: self.object.key.clone(),
+                message: format!(
+                    "part number {} is not between 1 and {}",
+                    part_number, MAX_PARTS
+                ),
+            });
+        }
+
+        let object = ObjectRequest {
+            headers: Default::default(),
+            ..self.object.clone()
+        };
+        self.presign(
+            "PUT",
+            &object,
+            &[
+                ("partNumber", &part_number.to_string()),
+                ("uploadId", upload_id),
+            ],
+        )
+    }
+
+    /// `UploadPart` for parts 1 to `part_count`.
+    pub fn upload_parts(
+        &self,
+        upload_id: &str,
+        part_count: u32,
+    ) -> Result<Vec<PresignedObject>, error::Error> {
+        (1..=part_count)
+            .map(|part_number| self.upload_part(upload_id, part_number))
+            .collect()
+    }
+
+    /// `CompleteMultipartUpload`. The body lists the uploaded parts (see `complete_body`).
+    pub fn complete(&self, upload_id: &str) -> Result<PresignedObject, error::Error> {
+        self.presign("POST", &self.bare_object(), &[("uploadId", upload_id)])
+    }
+
+    /// `AbortMultipartUpload`
+    pub fn abort(&self, upload_id: &str) -> Result<PresignedObject, error::Error> {
+        self.presign("DELETE", &self.bare_object(), &[("uploadId", upload_id)])
+    }
+
+    fn bare_object(&self) -> ObjectRequest {
+        ObjectRequest {
+            headers: Default::default(),
+            sse_customer_key: None,
+            ..self.object.clone()
+        }
+    }
+
+    fn presign(
+        &self,
+        method: &str,
+        object: &ObjectRequest,
+        query: &[(&str, &str)],
+    ) -> Result<PresignedObject, error::Error> {
+        let request = object_request(method, object, query)?;
+        let url = presigner::presign_with_key(
+            &request,
+            &self.params,
+            &self.credentials,
+            &self.signing_key,
+        );
+        Ok(PresignedObject::new(request, url))
+    }
+}
+
+/// The upload id from a `CreateMultipartUpload` response.
+pub fn upload_id(response: &str) -> Option<String> {
+    xml::element_text(response, "UploadId")
+}
+
+/// The body of a `CompleteMultipartUpload` request, from the number and `ETag` of each part.
+pub fn complete_body(parts: &[(u32, String)]) -> String {
+    let mut body = String::from("<CompleteMultipartUpload>");
+    for (part_number, etag) in parts {
+        body.push_str(&format!(
+            "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+            part_number,
+            xml::escape(etag)
+        ));
+    }
+    body.push_str("</CompleteMultipartUpload>");
+    body
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    use crate::error::Error;
+    use crate::s3::multipart::*;
+    use crate::s3::test_util::{assert_verifies, credentials};
+
+    fn presigner() -> MultipartPresigner {
+        let mut headers = BTreeMap::new();
+        headers.insert("Content-Type".to_string(), vec!["video/mp4".to_string()]);
+        let object = ObjectRequest {
+            bucket: "examplebucket".to_string(),
+            key: "videos/large.mp4".to_string(),
+            region: "us-east-1".to_string(),
+            headers,
+            sse_customer_key: Some(vec![7; 32]),
+            ..Default::default()
+        };
+        MultipartPresigner::new(&object, &credentials(), &Duration::from_secs(3600)).unwrap()
+    }
+
+    #[test]
+    fn test_workflow() {
+        let presigner = presigner();
+        let base = "https://examplebucket.s3.us-east-1.amazonaws.com/videos/large.mp4";
+
+        let create = presigner.create().unwrap();
+        assert_eq!("POST", create.method);
+        assert!(create
+            .url
+            .starts_with(&format!("{}?X-Amz-Algorithm=", base)));
+        assert!(create.url.contains("&uploads=&"));
+        assert_eq!(4, create.headers.len());
+        assert_verifies(&create);
+
+        let parts = presigner.upload_parts("upload-1", 3).unwrap();
+        assert_eq!(3, parts.len());
+        for (i, part) in parts.iter().enumerate() {
+            assert_eq!("PUT", part.method);
+            assert!(part
+                .url
+                .contains(&format!("&partNumber={}&uploadId=upload-1&", i + 1)));
+            assert!(!part.headers.contains_key("Content-Type"));
+            assert_eq!(3, part.headers.len());
+            assert_verifies(part);
+        }
+
+        let complete = presigner.complete("upload-1").unwrap();
+        assert_eq!("POST", complete.method);
+        assert!(complete.url.contains("&uploadId=upload-1&"));
+        assert!(complete.headers.is_empty());
+        assert_verifies(&complete);
+
+        let abort = presigner.abort("upload-1").unwrap();
+        assert_eq!("DELETE", abort.method);
+        assert_verifies(&abort);
+    }
+
+    #[test]
+    fn test_part_numbers() {
+        let presigner = presigner();
+        assert!(matches!(
+            presigner.upload_part("upload-1", 0),
+            Err(Error::InvalidObject { .. })
+        ));
+        assert!(presigner.upload_part("upload-1", MAX_PARTS).is_ok());
+        assert!(matches!(
+            presigner.upload_part("upload-1", MAX_PARTS + 1),
+            Err(Error::InvalidObject { .. })
+        ));
+    }
+
+    #[test]
+    fn test_documents() {
+        let response = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <InitiateMultipartUploadResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">\
+            <Bucket>examplebucket</Bucket><Key>videos/large.mp4</Key>\
+            <UploadId>VXBsb2FkIElE&amp;1</UploadId></InitiateMultipartUploadResult>";
+        assert_eq!(Some("VXBsb2FkIElE&1".to_string()), upload_id(response));
+
+        assert_eq!(
+            "<CompleteMultipartUpload>\
+             <Part><PartNumber>1</PartNumber><ETag>\"a54357aff0632cce46d942af68356b38\"</ETag></Part>\
+             <Part><PartNumber>2</PartNumber><ETag>\"0c78aef83f66abc1fa1e8477f296d394\"</ETag></Part>\
+             </CompleteMultipartUpload>",
+            complete_body(&[
+                (1, "\"a54357aff0632cce46d942af68356b38\"".to_string()),
+                (2, "\"0c78aef83f66abc1fa1e8477f296d394\"".to_string()),
+            ])
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use chrono::{DateTime, Utc};
+
+use crate::xml;
+
+/// Error codes that AWS services return when a request's timestamp is too far from the server's
+/// clock. `SignatureDoesNotMatch` and `AuthFailure` are also returned for bad credentials, so a
+/// skew should only be assumed if the server's time is known to differ from ours.
+pub const CLOCK_SKEW_CODES: [&str; 6] = [
+    "RequestTimeTooSkewed",
+    "RequestExpired",
+    "RequestInTheFuture",
+    "InvalidSignatureException",
+    "SignatureDoesNotMatch",
+    "AuthFailure",
+];
+
+/// The error document returned by an AWS service, either in the S3 format
+/// (`<Error><Code>...</Code></Error>`) or the query API format
+/// (`<ErrorResponse><Error><Code>...</Code></Error><RequestId>...</RequestId></ErrorResponse>`).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceError {
+    pub code: String,
+    pub message: Option<String>,
+    pub request_id: Option<String>,
+    /// The server's time, which S3 includes in `RequestTimeTooSkewed` errors.
+    pub server_time: Option<DateTime<Utc>>,
+}
+
+impl ServiceError {
+    /// Parse an error document, returning `None` if it doesn't contain an error code.
+    pub fn parse(document: &str) -> Option<ServiceError> {
+        let code = xml::element_text(document, "Code").filter(|code| !code.is_empty())?;
+        let server_time = xml::element_text(document, "ServerTime")
+            .and_then(|time| DateTime::parse_from_rfc3339(time.trim()).ok())
+            .map(|time| time.with_timezone(&Utc));
+        Some(ServiceError {
+            code,
+            message: xml::element_text(document, "Message"),
+            request_id: xml::element_text(document, "RequestId"),
+            server_time,
+        })
+    }
+
+    /// Whether the error code is one of `CLOCK_SKEW_CODES`.
+    pub fn is_clock_skew(&self) -> bool {
+        CLOCK_SKEW_CODES.contains(&self.code.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use crate::service_error::*;
+
+    #[test]
+    fn test_s3_error() {
+        let document = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <Error><Code>RequestTimeTooSkewed</Code>\
+            <Message>The difference between the request time and the current time is too large.</Message>\
+            <RequestTime>20150830T123600Z</RequestTime>\
+            <ServerTime>2015-08-30T13:06:00Z</ServerTime>\
+            <MaxAllowedSkewMilliseconds>900000</MaxAllowedSkewMilliseconds>\
+            <RequestId>4442587FB7D0A2F9</RequestId><HostId>host</HostId></Error>";
+        let error = ServiceError::parse(document).unwrap();
+        assert_eq!("RequestTimeTooSkewed", error.code);
+        assert_eq!(Some("4442587FB7D0A2F9".to_string()), error.request_id);
+        assert_eq!(
+            Some(Utc.ymd_opt(2015, 8, 30).and_hms_opt(13, 6, 0).unwrap()),
+            error.server_time
+        );
+        assert!(error.is_clock_skew());
+    }
+
+    #[test]
+    fn test_query_error() {
+        let document = "<ErrorResponse xmlns=\"https://sts.amazonaws.com/doc/2011-06-15/\">\
+            <Error><Type>Sender</Type><Code>AccessDenied</Code>\
+            <Message>User is not authorized to perform: sts:AssumeRole</Message></Error>\
+            <RequestId>c6104cbe-af31-11e0-8154-cbc7ccf896c7</RequestId></ErrorResponse>";
+        assert_eq!(
+            Some(ServiceError {
+                code: "AccessDenied".to_string(),
+                message: Some("User is not authorized to perform: sts:AssumeRole".to_string()),
+                request_id: Some("c6104cbe-af31-11e0-8154-cbc7ccf896c7".to_string()),
+                server_time: None,
+            }),
+            ServiceError::parse(document)
+        );
+        assert!(!ServiceError::parse(document).unwrap().is_clock_skew());
+
+        assert_eq!(None, ServiceError::parse(""));
+        assert_eq!(None, ServiceError::parse("<Error><Code/></Error>"));
+    }
+}
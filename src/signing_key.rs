@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+
+use crate::presigner::{derive_signing_key, SigningParams};
+use crate::util::{hash, to_date_string};
+
+/// The cache is cleared when it grows beyond this many keys (e.g. when rotating through many
+/// credentials), to bound its memory use.
+pub const MAX_ENTRIES: usize = 1024;
+
+#[derive(Hash, PartialEq, Eq)]
+struct CacheKey {
+    // A hash of the secret access key, so that the cache doesn't hold another copy of the secret
+    secret_hash: Vec<u8>,
+    date: String,
+    region: String,
+    service_name: String,
+}
+
+struct Entries {
+    keys: HashMap<CacheKey, Vec<u8>>,
+    latest_date: String,
+}
+
+/// A thread-safe cache of signing keys. A signing key depends only on the secret access key, date,
+/// region and service, so one derivation can be reused for every request signed that day. Keys for
+/// earlier dates are evicted when the date rolls over, and keys for dates before the latest one
+/// seen (e.g. when signing with a backdated clock) are derived without being cached.
+pub struct SigningKeyCache {
+    entries: Mutex<Entries>,
+}
+
+impl SigningKeyCache {
+    pub fn new() -> SigningKeyCache {
+        SigningKeyCache {
+            entries: Mutex::new(Entries {
+                keys: HashMap::new(),
+                latest_date: String::new(),
+            }),
+        }
+    }
+
+    /// The signing key for the arguments of `derive_signing_key`, deriving it if it isn't cached.
+    pub fn signing_key(
+        &self,
+        secret_access_key: &str,
+        timestamp: &DateTime<Utc>,
+        region: &str,
+        service_name: &str,
+    ) -> Vec<u8> {
+        let key = CacheKey {
+            secret_hash: hash(secret_access_key.as_bytes()),
+            date: to_date_string(timestamp),
+            region: region.to_string(),
+            service_name: service_name.to_string(),
+        };
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(signing_key) = entries.keys.get(&key) {
+            return signing_key.clone();
+        }
+
+        if key.date < entries.latest_date {
+            return derive_signing_key(secret_access_key, timestamp, region, service_name);
+        }
+        if key.date > entries.latest_date {
+            let latest_date = key.date.clone();
+            entries.keys.retain(|k, _| k.date == latest_date);
+            entries.latest_date = latest_date;
+        }
+        if entries.keys.len() >= MAX_ENTRIES {
+            entries.keys.clear();
+        }
+
+        let signing_key = derive_signing_key(secret_access_key, timestamp, region, service_name);
+        entries.keys.insert(key, signing_key.clone());
+        signing_key
+    }
+
+    /// The signing key for signing a request with `params`.
+    pub fn signing_key_for(&self, secret_access_key: &str, params: &SigningParams) -> Vec<u8> {
+        self.signing_key(
+            secret_access_key,
+            &params.timestamp,
+            &params.region,
+            &params.service_name,
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for SigningKeyCache {
+    fn default() -> SigningKeyCache {
+        SigningKeyCache::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use url::Url;
+
+    use crate::presigner::{
+        presign, presign_with_key, HeaderPolicy, Payload, PresignerRequest, SigningCredentials,
+    };
+    use crate::signing_key::*;
+
+    const SECRET: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+
+    #[test]
+    fn test_cache() {
+        let cache = SigningKeyCache::new();
+        let day1 = Utc.ymd_opt(2015, 8, 30).and_hms_opt(0, 0, 0).unwrap();
+        let later = Utc.ymd_opt(2015, 8, 30).and_hms_opt(23, 59, 59).unwrap();
+
+        let signing_key = cache.signing_key(SECRET, &day1, "us-east-1", "iam");
+        assert_eq!(
+            derive_signing_key(SECRET, &day1, "us-east-1", "iam"),
+            signing_key
+        );
+        assert_eq!(
+            signing_key,
+            cache.signing_key(SECRET, &later, "us-east-1", "iam")
+        );
+        assert_eq!(1, cache.len());
+
+        cache.signing_key(SECRET, &day1, "us-west-2", "iam");
+        cache.signing_key(SECRET, &day1, "us-east-1", "s3");
+        cache.signing_key("other", &day1, "us-east-1", "iam");
+        assert_eq!(4, cache.len());
+
+        let day2 = Utc.ymd_opt(2015, 8, 31).and_hms_opt(0, 0, 0).unwrap();
+        assert_eq!(
+            derive_signing_key(SECRET, &day2, "us-east-1", "iam"),
+            cache.signing_key(SECRET, &day2, "us-east-1", "iam")
+        );
+        assert_eq!(1, cache.len());
+
+        // Keys for earlier dates are still derived, but not cached
+        assert_eq!(
+            derive_signing_key(SECRET, &day1, "us-east-1", "iam"),
+            cache.signing_key(SECRET, &day1, "us-east-1", "iam")
+        );
+        cache.signing_key(SECRET, &day1, "eu-west-1", "iam");
+        assert_eq!(1, cache.len());
+    }
+
+    #[test]
+    fn test_presign_with_key() {
+        let mut headers = BTreeMap::new();
+        headers.insert(
+            "Host".to_string(),
+            vec!["example.amazonaws.com".to_string()],
+        );
+        let request = PresignerRequest {
+            request_method: "GET".to_string(),
+            url: Url::parse("https://example.amazonaws.com/").unwrap(),
+            headers,
+            payload: Payload::Bytes(vec![]),
+        };
+        let params = SigningParams {
+            double_encode_url: true,
+            normalize_path: true,
+            content_sha256_header: false,
+            header_policy: HeaderPolicy::All,
+            region: "us-east-1".to_string(),
+            service_name: "service".to_string(),
+            expiry: Duration::from_secs(60),
+            timestamp: Utc.ymd_opt(2015, 8, 30).and_hms_opt(12, 36, 0).unwrap(),
+        };
+        let credentials = SigningCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: SECRET.to_string(),
+            session_token: None,
+            expiration: None,
+        };
+
+        let cache = Arc::new(SigningKeyCache::new());
+        let expected = presign(&request, &params, &credentials);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                let params = params.clone();
+                thread::spawn(move || cache.signing_key_for(SECRET, &params))
+            })
+            .collect();
+        for thread in threads {
+            let signing_key = thread.join().unwrap();
+            assert_eq!(
+                expected,
+                presign_with_key(&request, &params, &credentials, &signing_key)
+            );
+        }
+        assert_eq!(1, cache.len());
+    }
+}
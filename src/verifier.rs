@@ -0,0 +1,573 @@
+use core::fmt;
+use std::collections::BTreeMap;
+use std::error;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use url::Url;
+
+use crate::clock::{Clock, SystemClock};
+use crate::presigner::{
+    build_canonical_request, build_credential_scope, build_string_to_sign, canonical_query_string,
+    derive_signing_key, url_query_params, HeaderPolicy, PresignerRequest, SigningParams, ALGORITHM,
+};
+use crate::util::*;
+
+/// Presigned URLs can be valid for at most 7 days.
+pub const MAX_EXPIRES: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+pub struct VerifyOptions {
+    /// Must match the value used when presigning (false for S3, true for everything else).
+    pub double_encode_url: bool,
+    /// Must match the value used when presigning (false for S3, true for everything else).
+    pub normalize_path: bool,
+    /// How far in the future `X-Amz-Date` may be, to allow for clock differences.
+    pub max_clock_skew: Duration,
+}
+
+/// The credential scope of a signature: the date, region and service the signing key is valid for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CredentialScope {
+    pub date: String,
+    pub region: String,
+    pub service_name: String,
+}
+
+/// The signing details of a presigned URL (or RDS authentication token), as returned by
+/// `parse_presigned_url`. Its `Debug` output doesn't include the session token.
+#[derive(Clone, PartialEq)]
+pub struct PresignedUrl {
+    pub url: Url,
+    pub access_key_id: String,
+    pub scope: CredentialScope,
+    pub signed_headers: Vec<String>,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub session_token: Option<String>,
+    pub signature: String,
+    /// The non-`X-Amz-*` query parameters (e.g. `Action` and `DBUser` for an RDS token).
+    pub params: BTreeMap<String, Vec<String>>,
+}
+
+impl PresignedUrl {
+    pub fn has_session_token(&self) -> bool {
+        self.session_token.is_some()
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        now > self.expires_at
+    }
+
+    /// The first value of a non-`X-Amz-*` query parameter.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .get(name)
+            .and_then(|values| values.first())
+            .map(|v| v.as_str())
+    }
+}
+
+impl fmt::Debug for PresignedUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut url = self.url.clone();
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(name, value)| match name.as_ref() {
+                "X-Amz-Security-Token" => (name.to_string(), REDACTED.to_string()),
+                _ => (name.to_string(), value.to_string()),
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(query);
+
+        f.debug_struct("PresignedUrl")
+            .field("url", &url.as_str())
+            .field("access_key_id", &self.access_key_id)
+            .field("scope", &self.scope)
+            .field("signed_headers", &self.signed_headers)
+            .field("issued_at", &self.issued_at)
+            .field("expires_at", &self.expires_at)
+            .field(
+                "session_token",
+                &self.session_token.as_ref().map(|_| REDACTED),
+            )
+            .field("signature", &self.signature)
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+const REDACTED: &str = "<redacted>";
+
+/// Why a presigned request was rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    MalformedUrl(String),
+    MissingParameter(&'static str),
+    UnsupportedAlgorithm(String),
+    MalformedCredential(String),
+    MalformedDate(String),
+    /// The date in the credential scope doesn't match `X-Amz-Date`.
+    CredentialDateMismatch,
+    MalformedExpires(String),
+    ExpiresOutOfRange(u64),
+    NotYetValid {
+        issued_at: DateTime<Utc>,
+    },
+    Expired {
+        expired_at: DateTime<Utc>,
+    },
+    /// `host` must always be one of the signed headers.
+    HostNotSigned,
+    MissingSignedHeader(String),
+    UnknownAccessKey(String),
+    /// The signature didn't match. The canonical request and string to sign that we computed are
+    /// included so that they can be compared with the client's.
+    SignatureMismatch {
+        canonical_request: String,
+        string_to_sign: String,
+    },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::MalformedUrl(url) => write!(f, "malformed URL {}", url),
+            Rejection::MissingParameter(name) => write!(f, "missing query parameter {}", name),
+            Rejection::UnsupportedAlgorithm(algorithm) => {
+                write!(f, "unsupported algorithm {}", algorithm)
+            }
+            Rejection::MalformedCredential(credential) => {
+                write!(f, "malformed credential {}", credential)
+            }
+            Rejection::MalformedDate(date) => write!(f, "malformed X-Amz-Date {}", date),
+            Rejection::CredentialDateMismatch => {
+                write!(f, "credential scope date does not match X-Amz-Date")
+            }
+            Rejection::MalformedExpires(expires) => {
+                write!(f, "malformed X-Amz-Expires {}", expires)
+            }
+            Rejection::ExpiresOutOfRange(expires) => write!(
+                f,
+                "X-Amz-Expires of {} seconds must be between 1 and {}",
+                expires,
+                MAX_EXPIRES.as_secs()
+            ),
+            Rejection::NotYetValid { issued_at } => {
+                write!(f, "request is not valid until {}", issued_at.to_rfc3339())
+            }
+            Rejection::Expired { expired_at } => {
+                write!(f, "request expired at {}", expired_at.to_rfc3339())
+            }
+            Rejection::HostNotSigned => write!(f, "host header is not signed"),
+            Rejection::MissingSignedHeader(name) => {
+                write!(f, "signed header {} is not present", name)
+            }
+            Rejection::UnknownAccessKey(access_key_id) => {
+                write!(f, "unknown access key {}", access_key_id)
+            }
+            Rejection::SignatureMismatch { .. } => write!(f, "signature does not match"),
+        }
+    }
+}
+
+impl error::Error for Rejection {}
+
+/// Parse a presigned URL, or an RDS authentication token (which has no scheme), without
+/// verifying it. Only the structure is checked: a token that `verify` would reject (e.g. because
+/// it has expired, or its expiry is out of range) can still be parsed and inspected.
+pub fn parse_presigned_url(url: &str) -> Result<PresignedUrl, Rejection> {
+    let with_scheme = if url.contains("://") {
+        url.to_string()
+    } else {
+        format!("https://{}", url)
+    };
+    let url = Url::parse(&with_scheme).map_err(|_| Rejection::MalformedUrl(url.to_string()))?;
+    parse_url(&url)
+}
+
+fn parse_url(url: &Url) -> Result<PresignedUrl, Rejection> {
+    let mut params = url_query_params(url);
+    let mut take = |name: &'static str| {
+        params
+            .remove(name)
+            .and_then(|values| values.into_iter().next())
+            .ok_or(Rejection::MissingParameter(name))
+    };
+
+    let algorithm = take("X-Amz-Algorithm")?;
+    let credential = take("X-Amz-Credential")?;
+    let date = take("X-Amz-Date")?;
+    let expires = take("X-Amz-Expires")?;
+    let signed_headers = take("X-Amz-SignedHeaders")?;
+    let signature = take("X-Amz-Signature")?;
+    let session_token = take("X-Amz-Security-Token").ok();
+
+    if algorithm != ALGORITHM {
+        return Err(Rejection::UnsupportedAlgorithm(algorithm));
+    }
+
+    let parts: Vec<&str> = credential.split('/').collect();
+    if parts.len() != 5 || parts[4] != "aws4_request" || parts.iter().any(|s| s.is_empty()) {
+        return Err(Rejection::MalformedCredential(credential.clone()));
+    }
+    let scope = CredentialScope {
+        date: parts[1].to_string(),
+        region: parts[2].to_string(),
+        service_name: parts[3].to_string(),
+    };
+
+    let issued_at = Utc
+        .datetime_from_str(&date, "%Y%m%dT%H%M%SZ")
+        .map_err(|_| Rejection::MalformedDate(date.clone()))?;
+    let expires_at = expires
+        .parse::<i64>()
+        .ok()
+        .filter(|secs| (0..=i64::MAX / 1000).contains(secs))
+        .and_then(|secs| issued_at.checked_add_signed(chrono::Duration::seconds(secs)))
+        .ok_or_else(|| Rejection::MalformedExpires(expires.clone()))?;
+
+    Ok(PresignedUrl {
+        url: url.clone(),
+        access_key_id: parts[0].to_string(),
+        scope,
+        signed_headers: signed_headers.split(';').map(|h| h.to_string()).collect(),
+        issued_at,
+        expires_at,
+        session_token,
+        signature,
+        params,
+    })
+}
+
+/// Verify a request that was presigned with `presigner::presign`, as an AWS service would.
+///
+/// `secret_lookup` returns the secret access key for an access key id (or `None` if it is not
+/// known). The request's headers must include every header named in `X-Amz-SignedHeaders`.
+pub fn verify<F>(
+    request: &PresignerRequest,
+    options: &VerifyOptions,
+    secret_lookup: F,
+) -> Result<PresignedUrl, Rejection>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    verify_with_clock(&SystemClock, request, options, secret_lookup)
+}
+
+/// `verify`, with `X-Amz-Date` and `X-Amz-Expires` checked against the time from `clock`.
+pub fn verify_with_clock<F>(
+    clock: &dyn Clock,
+    request: &PresignerRequest,
+    options: &VerifyOptions,
+    secret_lookup: F,
+) -> Result<PresignedUrl, Rejection>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let presigned = parse_url(&request.url)?;
+
+    if to_date_string(&presigned.issued_at) != presigned.scope.date {
+        return Err(Rejection::CredentialDateMismatch);
+    }
+    let expires_secs = (presigned.expires_at - presigned.issued_at).num_seconds() as u64;
+    if expires_secs == 0 || expires_secs > MAX_EXPIRES.as_secs() {
+        return Err(Rejection::ExpiresOutOfRange(expires_secs));
+    }
+
+    let max_clock_skew = chrono::Duration::from_std(options.max_clock_skew)
+        .unwrap_or_else(|_| chrono::Duration::zero());
+    let now = clock.now();
+    if now + max_clock_skew < presigned.issued_at {
+        return Err(Rejection::NotYetValid {
+            issued_at: presigned.issued_at,
+        });
+    }
+    if presigned.is_expired(now) {
+        return Err(Rejection::Expired {
+            expired_at: presigned.expires_at,
+        });
+    }
+
+    if !presigned.signed_headers.iter().any(|h| h == "host") {
+        return Err(Rejection::HostNotSigned);
+    }
+    let mut headers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for name in &presigned.signed_headers {
+        let values: Vec<String> = request
+            .headers
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(name))
+            .flat_map(|(_, v)| v.iter().cloned())
+            .collect();
+        if values.is_empty() {
+            return Err(Rejection::MissingSignedHeader(name.to_string()));
+        }
+        headers.insert(name.to_string(), values);
+    }
+
+    let secret_access_key = secret_lookup(&presigned.access_key_id)
+        .ok_or_else(|| Rejection::UnknownAccessKey(presigned.access_key_id.clone()))?;
+
+    let issued_at = presigned.issued_at;
+    let region = &presigned.scope.region;
+    let service_name = &presigned.scope.service_name;
+    let params = SigningParams {
+        double_encode_url: options.double_encode_url,
+        normalize_path: options.normalize_path,
+        content_sha256_header: false,
+        header_policy: HeaderPolicy::All,
+        region: region.to_string(),
+        service_name: service_name.to_string(),
+        expiry: (presigned.expires_at - issued_at)
+            .to_std()
+            .unwrap_or_default(),
+        timestamp: issued_at,
+    };
+    let mut query_params = url_query_params(&request.url);
+    query_params.remove("X-Amz-Signature");
+    let canonical_request = build_canonical_request(
+        request,
+        &params,
+        &canonical_query_string(&query_params),
+        &headers,
+        &request.payload.hash(),
+    );
+    let credential_scope = build_credential_scope(&issued_at, region, service_name);
+    let string_to_sign = build_string_to_sign(&issued_at, &credential_scope, &canonical_request);
+    let k_signing = derive_signing_key(&secret_access_key, &issued_at, region, service_name);
+    let expected_signature = sign(&k_signing, &string_to_sign);
+
+    if !constant_time_eq(
+        expected_signature.as_bytes(),
+        presigned.signature.as_bytes(),
+    ) {
+        return Err(Rejection::SignatureMismatch {
+            canonical_request,
+            string_to_sign,
+        });
+    }
+
+    Ok(presigned)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    use chrono::{DateTime, TimeZone, Utc};
+    use url::Url;
+
+    use crate::clock::FixedClock;
+    use crate::presigner::{
+        presign, HeaderPolicy, Payload, PresignerRequest, SigningCredentials, SigningParams,
+    };
+    use crate::verifier::*;
+
+    fn timestamp() -> DateTime<Utc> {
+        Utc.ymd_opt(2020, 1, 1).and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    fn request(url: &str) -> PresignerRequest {
+        let mut headers = BTreeMap::new();
+        headers.insert("Host".to_string(), vec!["example.com".to_string()]);
+        PresignerRequest {
+            request_method: "GET".to_string(),
+            url: Url::parse(url).unwrap(),
+            headers,
+            payload: Payload::Bytes(vec![]),
+        }
+    }
+
+    fn presigned_url() -> String {
+        let params = SigningParams {
+            double_encode_url: true,
+            normalize_path: true,
+            content_sha256_header: false,
+            header_policy: HeaderPolicy::All,
+            region: "us-east-1".to_string(),
+            service_name: "service".to_string(),
+            expiry: Duration::from_secs(300),
+            timestamp: timestamp(),
+        };
+        let credentials = SigningCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: Some("token".to_string()),
+            expiration: None,
+        };
+        presign(
+            &request("https://example.com/some path/file?b=2&a=1+1"),
+            &params,
+            &credentials,
+        )
+    }
+
+    fn options() -> VerifyOptions {
+        VerifyOptions {
+            double_encode_url: true,
+            normalize_path: true,
+            max_clock_skew: Duration::from_secs(60),
+        }
+    }
+
+    fn lookup(access_key_id: &str) -> Option<String> {
+        if access_key_id == "AKIDEXAMPLE" {
+            Some("secret".to_string())
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let verified = verify_with_clock(
+            &FixedClock(timestamp()),
+            &request(&presigned_url()),
+            &options(),
+            lookup,
+        )
+        .unwrap();
+        assert_eq!("AKIDEXAMPLE", verified.access_key_id);
+        assert_eq!("us-east-1", verified.scope.region);
+        assert_eq!("service", verified.scope.service_name);
+        assert_eq!(timestamp(), verified.issued_at);
+        assert_eq!(
+            timestamp() + chrono::Duration::seconds(300),
+            verified.expires_at
+        );
+        assert_eq!(Some("token".to_string()), verified.session_token);
+    }
+
+    #[test]
+    fn test_tampered() {
+        let url = presigned_url().replace("b=2", "b=3");
+        match verify_with_clock(&FixedClock(timestamp()), &request(&url), &options(), lookup) {
+            Err(Rejection::SignatureMismatch {
+                canonical_request, ..
+            }) => assert!(canonical_request.contains("b=3")),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rejections() {
+        let url = presigned_url();
+
+        let late = timestamp() + chrono::Duration::seconds(301);
+        assert!(matches!(
+            verify_with_clock(&FixedClock(late), &request(&url), &options(), lookup),
+            Err(Rejection::Expired { .. })
+        ));
+
+        let early = timestamp() - chrono::Duration::seconds(61);
+        assert!(matches!(
+            verify_with_clock(&FixedClock(early), &request(&url), &options(), lookup),
+            Err(Rejection::NotYetValid { .. })
+        ));
+
+        assert!(matches!(
+            verify_with_clock(&FixedClock(timestamp()), &request(&url), &options(), |_| {
+                None
+            }),
+            Err(Rejection::UnknownAccessKey(_))
+        ));
+
+        let mut no_host = request(&url);
+        no_host.headers.clear();
+        assert_eq!(
+            Err(Rejection::MissingSignedHeader("host".to_string())),
+            verify_with_clock(&FixedClock(timestamp()), &no_host, &options(), lookup)
+        );
+
+        let unsigned = url.split("&X-Amz-Signature").next().unwrap();
+        assert_eq!(
+            Err(Rejection::MissingParameter("X-Amz-Signature")),
+            verify_with_clock(
+                &FixedClock(timestamp()),
+                &request(unsigned),
+                &options(),
+                lookup
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_presigned_url() {
+        let parsed = parse_presigned_url(&presigned_url()).unwrap();
+        assert_eq!("AKIDEXAMPLE", parsed.access_key_id);
+        assert_eq!(
+            CredentialScope {
+                date: "20200101".to_string(),
+                region: "us-east-1".to_string(),
+                service_name: "service".to_string(),
+            },
+            parsed.scope
+        );
+        assert_eq!(vec!["host"], parsed.signed_headers);
+        assert!(parsed.has_session_token());
+        assert_eq!(64, parsed.signature.len());
+        assert_eq!(Some("1 1"), parsed.param("a"));
+        assert!(!parsed.is_expired(timestamp()));
+        assert!(parsed.is_expired(timestamp() + chrono::Duration::minutes(6)));
+
+        let debug = format!("{:?}", parsed);
+        assert!(debug.contains("X-Amz-Security-Token=%3Credacted%3E"));
+        assert!(debug.contains("session_token: Some(\"<redacted>\")"));
+        assert!(!debug.contains("token\""));
+    }
+
+    #[test]
+    fn test_parse_rds_token() {
+        let token = "mydb.123456789012.us-east-1.rds.amazonaws.com:5432/?Action=connect\
+                     &DBUser=jane_doe&X-Amz-Algorithm=AWS4-HMAC-SHA256\
+                     &X-Amz-Credential=AKIDEXAMPLE%2F20200101%2Fus-west-2%2Frds-db%2Faws4_request\
+                     &X-Amz-Date=20200101T120000Z&X-Amz-Expires=900&X-Amz-SignedHeaders=host\
+                     &X-Amz-Signature=0123456789abcdef";
+        let parsed = parse_presigned_url(token).unwrap();
+        assert_eq!("us-west-2", parsed.scope.region);
+        assert_eq!("rds-db", parsed.scope.service_name);
+        assert_eq!(Some("jane_doe"), parsed.param("DBUser"));
+        assert_eq!(
+            Utc.ymd_opt(2020, 1, 1).and_hms_opt(12, 15, 0).unwrap(),
+            parsed.expires_at
+        );
+        assert!(!parsed.has_session_token());
+
+        // Tokens that can't be valid are still parsed, so that they can be inspected
+        let mismatched = token.replace("20200101T", "20200102T");
+        assert_eq!(
+            "20200101",
+            parse_presigned_url(&mismatched).unwrap().scope.date
+        );
+        assert_eq!(
+            Err(Rejection::CredentialDateMismatch),
+            verify_with_clock(
+                &FixedClock(timestamp()),
+                &request(&format!("https://{}", mismatched)),
+                &options(),
+                lookup
+            )
+        );
+
+        let too_long = token.replace("X-Amz-Expires=900", "X-Amz-Expires=604801");
+        assert!(parse_presigned_url(&too_long).is_ok());
+        assert_eq!(
+            Err(Rejection::ExpiresOutOfRange(604801)),
+            verify_with_clock(
+                &FixedClock(timestamp()),
+                &request(&format!("https://{}", too_long)),
+                &options(),
+                lookup
+            )
+        );
+        assert!(matches!(
+            parse_presigned_url(&token.replace("X-Amz-Expires=900", "X-Amz-Expires=-1")),
+            Err(Rejection::MalformedExpires(_))
+        ));
+    }
+}
@@ -0,0 +1,112 @@
+//! Just enough XML to pull values out of STS and S3 responses, and to write S3 request bodies.
+
+/// The unescaped text of the first element with the given name, if there is one.
+pub fn element_text(document: &str, name: &str) -> Option<String> {
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+
+    let mut search_from = 0;
+    while let Some(offset) = document[search_from..].find(&open) {
+        let start = search_from + offset + open.len();
+        let rest = &document[start..];
+        // Make sure we matched the whole tag name (e.g. not <CodeBlock> when looking for <Code>)
+        match rest.chars().next() {
+            Some('>') => {
+                let end = rest.find(&close)?;
+                return Some(unescape(&rest[1..end]));
+            }
+            Some(c) if c.is_whitespace() => {
+                let tag_end = rest.find('>')?;
+                if rest[..tag_end].ends_with('/') {
+                    return Some(String::new());
+                }
+                let end = rest.find(&close)?;
+                return Some(unescape(&rest[tag_end + 1..end]));
+            }
+            Some('/') if rest[1..].starts_with('>') => return Some(String::new()),
+            _ => search_from = start,
+        }
+    }
+    None
+}
+
+/// Escape text for use as element content.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        result.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let semi = match rest.find(';') {
+            Some(semi) => semi,
+            None => break,
+        };
+        let entity = &rest[1..semi];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16)
+                .ok()
+                .and_then(std::char::from_u32),
+            _ if entity.starts_with('#') => entity[1..]
+                .parse::<u32>()
+                .ok()
+                .and_then(std::char::from_u32),
+            _ => None,
+        };
+        match decoded {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::xml::{element_text, escape};
+
+    #[test]
+    fn test_element_text() {
+        let document = r#"<?xml version="1.0"?>
+<Response xmlns="https://example.com/">
+  <CodeBlock>wrong</CodeBlock>
+  <Code>Value &amp; &lt;more&gt; &#65;&#x42;</Code>
+  <Attr kind="x">attributed</Attr>
+  <Empty/>
+</Response>"#;
+        assert_eq!(
+            Some("Value & <more> AB".to_string()),
+            element_text(document, "Code")
+        );
+        assert_eq!(
+            Some("attributed".to_string()),
+            element_text(document, "Attr")
+        );
+        assert_eq!(Some(String::new()), element_text(document, "Empty"));
+        assert_eq!(None, element_text(document, "Missing"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!("\"a\" &amp; &lt;b&gt;", escape("\"a\" & <b>"));
+        let document = format!("<Code>{}</Code>", escape("<&>"));
+        assert_eq!(Some("<&>".to_string()), element_text(&document, "Code"));
+    }
+}